@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+create table update_offsets
+(
+    id             int    not null
+        constraint update_offsets_pk
+            primary key,
+    last_update_id bigint not null
+);
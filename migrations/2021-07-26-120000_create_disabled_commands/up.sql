@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+create table disabled_commands
+(
+    chat_id bigint      not null,
+    command varchar(32) not null,
+    constraint disabled_commands_pk
+        primary key (chat_id, command)
+);
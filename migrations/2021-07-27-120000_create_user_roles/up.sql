@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+create table user_roles
+(
+    chat_id bigint      not null,
+    user_id bigint      not null,
+    role    varchar(32) not null,
+    constraint user_roles_pk
+        primary key (chat_id, user_id, role)
+);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+create table chat_texts
+(
+    chat_id  bigint      not null,
+    key      varchar(64) not null,
+    template text        not null,
+    constraint chat_texts_pk
+        primary key (chat_id, key)
+);
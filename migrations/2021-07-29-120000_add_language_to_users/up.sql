@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+alter table users
+    add column language varchar(8);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+create table chat_permissions
+(
+    chat_id bigint      not null
+        constraint chat_permissions_pk
+            primary key,
+    status  varchar(16) not null
+);
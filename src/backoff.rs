@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use rand::{thread_rng, Rng};
+
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+
+pub struct Backoff {
+    attempt: u32,
+    max_delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(max_delay: Duration) -> Self {
+        Self {
+            attempt: 0,
+            max_delay,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    // Doubles the delay on every failed attempt up to max_delay, then picks a random delay
+    // between the half of it and the full value so restarted bots don't retry in lockstep
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = INITIAL_DELAY
+            .checked_mul(2u32.saturating_pow(self.attempt))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        self.attempt = self.attempt.saturating_add(1);
+
+        let millis = delay.as_millis() as u64;
+        Duration::from_millis(thread_rng().gen_range(millis / 2..=millis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_within(delay: Duration, max: Duration) {
+        assert!(
+            delay >= max / 2 && delay <= max,
+            "{:?} is not within [{:?}, {:?}]",
+            delay,
+            max / 2,
+            max
+        );
+    }
+
+    #[test]
+    fn doubles_up_to_max_delay() {
+        let mut backoff = Backoff::new(Duration::from_secs(10));
+
+        for max in [1, 2, 4, 8, 10, 10, 10].iter() {
+            assert_within(backoff.next_delay(), Duration::from_secs(*max));
+        }
+    }
+
+    #[test]
+    fn stays_within_half_and_full_delay() {
+        for _ in 0..100 {
+            let mut backoff = Backoff::new(Duration::from_secs(60));
+            backoff.next_delay();
+            backoff.next_delay();
+
+            assert_within(backoff.next_delay(), Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn resets_after_success() {
+        let mut backoff = Backoff::new(Duration::from_secs(60));
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+
+        backoff.reset();
+
+        assert_within(backoff.next_delay(), INITIAL_DELAY);
+        assert_within(backoff.next_delay(), INITIAL_DELAY * 2);
+    }
+
+    #[test]
+    fn survives_many_failures() {
+        let mut backoff = Backoff::new(Duration::from_secs(30));
+        for _ in 0..100 {
+            backoff.next_delay();
+        }
+
+        assert_within(backoff.next_delay(), Duration::from_secs(30));
+    }
+}
@@ -1,20 +1,79 @@
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use liquid::Template;
+
+use crate::metrics;
+use crate::services::chat_permission::ChatPermission;
+use crate::services::chat_text::ChatText;
+
+// How long the values read from the database are kept. The bot updates them itself when they
+// change, so this only keeps the chats and users which are gone from piling up
+pub const DATABASE_CACHE_TIME: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CooldownKey {
+    User(u64),
+    Chat(i64),
+}
+
+// A command waiting for the next message of a user in a chat
+pub struct Conversation {
+    pub command: &'static str,
+    // What the command is waiting for, only the command knows what it means
+    pub state: String,
+}
+
+// The answer to an inline query about the weather in a location
+#[derive(Clone)]
+pub struct WeatherCard {
+    // The name of the location as OpenWeather knows it
+    pub title: String,
+    pub text: String,
+}
 
 pub struct Cache {
     afk: Mutex<HashMap<i64, (bool, i32)>>,
+    inline_weather: Mutex<HashMap<String, (Instant, WeatherCard)>>,
+    // (command name, key) -> the end of the cooldown
+    cooldowns: Mutex<HashMap<(&'static str, CooldownKey), Instant>>,
+    // chat id -> (cached at, admin user ids)
+    chat_admins: Mutex<HashMap<i64, (Instant, Vec<u64>)>>,
+    // (chat id, user id) -> (expires at, conversation)
+    conversations: Mutex<HashMap<(i64, u64), (Instant, Conversation)>>,
+    // user id -> (cached at, the language chosen with /language, None if they haven't chosen one)
+    languages: Mutex<HashMap<u64, (Instant, Option<String>)>>,
+    // chat id -> (cached at, the commands disabled in the chat)
+    disabled_commands: Mutex<HashMap<i64, (Instant, Vec<String>)>>,
+    // (chat id, text) -> (cached at, the template set with /settext, None if there's none)
+    chat_templates: Mutex<HashMap<(i64, ChatText), (Instant, Option<Arc<Template>>)>>,
+    // (scope, language) -> the (name, description) of the commands published with setMyCommands
+    published_commands: Mutex<HashMap<(String, Option<String>), Vec<(String, String)>>>,
+    // chat id -> (cached at, the permission from the database, None if the owners haven't been asked)
+    chat_permissions: Mutex<HashMap<i64, (Instant, Option<ChatPermission>)>>,
 }
 
 impl Cache {
     pub fn new() -> Self {
         Self {
             afk: Mutex::new(HashMap::new()),
+            inline_weather: Mutex::new(HashMap::new()),
+            cooldowns: Mutex::new(HashMap::new()),
+            chat_admins: Mutex::new(HashMap::new()),
+            conversations: Mutex::new(HashMap::new()),
+            languages: Mutex::new(HashMap::new()),
+            chat_permissions: Mutex::new(HashMap::new()),
+            disabled_commands: Mutex::new(HashMap::new()),
+            published_commands: Mutex::new(HashMap::new()),
+            chat_templates: Mutex::new(HashMap::new()),
         }
     }
 
     pub fn cache_afk_event_id(&self, user_id: i64, status: bool, event_id: i32) {
         let mut afk = self.afk.lock().unwrap();
         afk.insert(user_id, (status, event_id));
+        Self::update_afk_metric(&afk);
     }
 
     pub fn populate_afk_cache(&self, user_id_event_id_tuple: &[(i64, i32)]) {
@@ -22,6 +81,11 @@ impl Cache {
         for (user_id, event_id) in user_id_event_id_tuple.iter() {
             afk.insert(user_id.to_owned(), (true, event_id.to_owned()));
         }
+        Self::update_afk_metric(&afk);
+    }
+
+    fn update_afk_metric(afk: &HashMap<i64, (bool, i32)>) {
+        metrics::AFK_USERS.set(afk.values().filter(|(status, _)| *status).count() as i64);
     }
 
     pub fn get_afk_event_id(&self, user_id: i64) -> Option<i32> {
@@ -31,4 +95,340 @@ impl Cache {
             (true, event_id) => Some(event_id.to_owned()),
         }
     }
+
+    pub fn get_inline_weather(&self, query: &str, ttl: Duration) -> Option<WeatherCard> {
+        let inline_weather = self.inline_weather.lock().unwrap();
+        match inline_weather.get(&query.to_lowercase()) {
+            Some((cached_at, card)) if cached_at.elapsed() < ttl => Some(card.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn cache_inline_weather(&self, query: &str, card: WeatherCard, ttl: Duration) {
+        let mut inline_weather = self.inline_weather.lock().unwrap();
+        // Every keystroke is a new query, so drop the stale ones to keep the map small
+        inline_weather.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+        inline_weather.insert(query.to_lowercase(), (Instant::now(), card));
+    }
+
+    // None if the language of the user isn't cached yet
+    pub fn get_language(&self, user_id: u64) -> Option<Option<String>> {
+        let languages = self.languages.lock().unwrap();
+        match languages.get(&user_id) {
+            Some((cached_at, language)) if cached_at.elapsed() < DATABASE_CACHE_TIME => {
+                Some(language.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn cache_language(&self, user_id: u64, language: Option<String>) {
+        let mut languages = self.languages.lock().unwrap();
+        languages.retain(|_, (cached_at, _)| cached_at.elapsed() < DATABASE_CACHE_TIME);
+        languages.insert(user_id, (Instant::now(), language));
+    }
+
+    // None if the permission of the chat isn't cached yet
+    pub fn get_chat_permission(&self, chat_id: i64) -> Option<Option<ChatPermission>> {
+        let chat_permissions = self.chat_permissions.lock().unwrap();
+        match chat_permissions.get(&chat_id) {
+            Some((cached_at, permission)) if cached_at.elapsed() < DATABASE_CACHE_TIME => {
+                Some(*permission)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn cache_chat_permission(&self, chat_id: i64, permission: Option<ChatPermission>) {
+        let mut chat_permissions = self.chat_permissions.lock().unwrap();
+        chat_permissions.retain(|_, (cached_at, _)| cached_at.elapsed() < DATABASE_CACHE_TIME);
+        chat_permissions.insert(chat_id, (Instant::now(), permission));
+    }
+
+    pub fn get_chat_admins(&self, chat_id: i64, ttl: Duration) -> Option<Vec<u64>> {
+        let chat_admins = self.chat_admins.lock().unwrap();
+        match chat_admins.get(&chat_id) {
+            Some((cached_at, admins)) if cached_at.elapsed() < ttl => Some(admins.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn cache_chat_admins(&self, chat_id: i64, admins: Vec<u64>, ttl: Duration) {
+        let mut chat_admins = self.chat_admins.lock().unwrap();
+        chat_admins.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+        chat_admins.insert(chat_id, (Instant::now(), admins));
+    }
+
+    pub fn get_disabled_commands(&self, chat_id: i64) -> Option<Vec<String>> {
+        let disabled_commands = self.disabled_commands.lock().unwrap();
+        match disabled_commands.get(&chat_id) {
+            Some((cached_at, names)) if cached_at.elapsed() < DATABASE_CACHE_TIME => {
+                Some(names.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn cache_disabled_commands(&self, chat_id: i64, names: Vec<String>) {
+        let mut disabled_commands = self.disabled_commands.lock().unwrap();
+        disabled_commands.retain(|_, (cached_at, _)| cached_at.elapsed() < DATABASE_CACHE_TIME);
+        disabled_commands.insert(chat_id, (Instant::now(), names));
+    }
+
+    pub fn forget_disabled_commands(&self, chat_id: i64) {
+        self.disabled_commands.lock().unwrap().remove(&chat_id);
+    }
+
+    // None if the template of the chat isn't cached yet
+    pub fn get_chat_template(&self, chat_id: i64, text: ChatText) -> Option<Option<Arc<Template>>> {
+        let chat_templates = self.chat_templates.lock().unwrap();
+        match chat_templates.get(&(chat_id, text)) {
+            Some((cached_at, template)) if cached_at.elapsed() < DATABASE_CACHE_TIME => {
+                Some(template.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn cache_chat_template(
+        &self,
+        chat_id: i64,
+        text: ChatText,
+        template: Option<Arc<Template>>,
+    ) {
+        let mut chat_templates = self.chat_templates.lock().unwrap();
+        chat_templates.retain(|_, (cached_at, _)| cached_at.elapsed() < DATABASE_CACHE_TIME);
+        chat_templates.insert((chat_id, text), (Instant::now(), template));
+    }
+
+    pub fn forget_chat_template(&self, chat_id: i64, text: ChatText) {
+        self.chat_templates.lock().unwrap().remove(&(chat_id, text));
+    }
+
+    pub fn get_published_commands(
+        &self,
+        scope: &str,
+        language_code: Option<&str>,
+    ) -> Option<Vec<(String, String)>> {
+        self.published_commands
+            .lock()
+            .unwrap()
+            .get(&(scope.to_string(), language_code.map(String::from)))
+            .cloned()
+    }
+
+    pub fn cache_published_commands(
+        &self,
+        scope: &str,
+        language_code: Option<&str>,
+        commands: Vec<(String, String)>,
+    ) {
+        self.published_commands.lock().unwrap().insert(
+            (scope.to_string(), language_code.map(String::from)),
+            commands,
+        );
+    }
+
+    // Starts the cooldowns unless one of them is still running,
+    // in which case nothing is started and the time left is returned
+    pub fn start_cooldowns(
+        &self,
+        command: &'static str,
+        cooldowns: &[(CooldownKey, Duration)],
+    ) -> Option<Duration> {
+        let mut running = self.cooldowns.lock().unwrap();
+        let now = Instant::now();
+        running.retain(|_, until| *until > now);
+
+        let remaining = cooldowns
+            .iter()
+            .filter_map(|(key, _)| running.get(&(command, *key)))
+            .map(|until| until.duration_since(now))
+            .max();
+        if remaining.is_some() {
+            return remaining;
+        }
+
+        for (key, duration) in cooldowns.iter() {
+            running.insert((command, *key), now + *duration);
+        }
+
+        None
+    }
+
+    // Replaces the conversation the user had in the chat, if any
+    pub fn start_conversation(
+        &self,
+        chat_id: i64,
+        user_id: u64,
+        conversation: Conversation,
+        timeout: Duration,
+    ) {
+        let mut conversations = self.conversations.lock().unwrap();
+        let now = Instant::now();
+        conversations.retain(|_, (expires_at, _)| *expires_at > now);
+        conversations.insert((chat_id, user_id), (now + timeout, conversation));
+    }
+
+    pub fn end_conversation(&self, chat_id: i64, user_id: u64) -> Option<Conversation> {
+        self.take_conversation(chat_id, user_id)
+            .map(|(_, conversation)| conversation)
+    }
+
+    // Ends the conversation, the expiry is returned along with it to resume it later
+    pub fn take_conversation(&self, chat_id: i64, user_id: u64) -> Option<(Instant, Conversation)> {
+        let mut conversations = self.conversations.lock().unwrap();
+        match conversations.remove(&(chat_id, user_id)) {
+            Some((expires_at, conversation)) if expires_at > Instant::now() => {
+                Some((expires_at, conversation))
+            }
+            _ => None,
+        }
+    }
+
+    // Puts back a conversation from take_conversation, unless another one has started since then
+    pub fn resume_conversation(
+        &self,
+        chat_id: i64,
+        user_id: u64,
+        expires_at: Instant,
+        conversation: Conversation,
+    ) {
+        self.conversations
+            .lock()
+            .unwrap()
+            .entry((chat_id, user_id))
+            .or_insert((expires_at, conversation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn cooldown_blocks_until_window_is_over() {
+        let cache = Cache::new();
+        let cooldowns = [(CooldownKey::User(1), WINDOW)];
+
+        assert_eq!(cache.start_cooldowns("weather", &cooldowns), None);
+        assert!(cache.start_cooldowns("weather", &cooldowns).unwrap() <= WINDOW);
+
+        thread::sleep(WINDOW);
+        assert_eq!(cache.start_cooldowns("weather", &cooldowns), None);
+    }
+
+    #[test]
+    fn cooldowns_are_per_command_and_key() {
+        let cache = Cache::new();
+        let hour = Duration::from_secs(3600);
+
+        assert_eq!(
+            cache.start_cooldowns("weather", &[(CooldownKey::User(1), hour)]),
+            None
+        );
+        assert_eq!(
+            cache.start_cooldowns("shuffle", &[(CooldownKey::User(1), hour)]),
+            None
+        );
+        assert_eq!(
+            cache.start_cooldowns("weather", &[(CooldownKey::User(2), hour)]),
+            None
+        );
+        assert_eq!(
+            cache.start_cooldowns("weather", &[(CooldownKey::Chat(1), hour)]),
+            None
+        );
+    }
+
+    fn conversation() -> Conversation {
+        Conversation {
+            command: "set_my_location",
+            state: "location".to_string(),
+        }
+    }
+
+    #[test]
+    fn conversation_ends_once() {
+        let cache = Cache::new();
+        cache.start_conversation(-1, 10, conversation(), Duration::from_secs(60));
+
+        assert_eq!(
+            cache.end_conversation(-1, 10).map(|c| c.command),
+            Some("set_my_location")
+        );
+        assert!(cache.end_conversation(-1, 10).is_none());
+    }
+
+    #[test]
+    fn conversations_are_per_chat_and_user() {
+        let cache = Cache::new();
+        cache.start_conversation(-1, 10, conversation(), Duration::from_secs(60));
+
+        assert!(cache.end_conversation(-2, 10).is_none());
+        assert!(cache.end_conversation(-1, 11).is_none());
+        assert!(cache.end_conversation(-1, 10).is_some());
+    }
+
+    #[test]
+    fn conversation_expires() {
+        let cache = Cache::new();
+        cache.start_conversation(-1, 10, conversation(), WINDOW);
+
+        thread::sleep(WINDOW);
+        assert!(cache.end_conversation(-1, 10).is_none());
+    }
+
+    #[test]
+    fn resumed_conversation_keeps_expiry() {
+        let cache = Cache::new();
+        cache.start_conversation(-1, 10, conversation(), WINDOW);
+
+        let (expires_at, conversation) = cache.take_conversation(-1, 10).unwrap();
+        cache.resume_conversation(-1, 10, expires_at, conversation);
+        assert_eq!(
+            cache.take_conversation(-1, 10).map(|(at, _)| at),
+            Some(expires_at)
+        );
+
+        cache.resume_conversation(-1, 10, expires_at, self::conversation());
+        thread::sleep(WINDOW);
+        assert!(cache.end_conversation(-1, 10).is_none());
+    }
+
+    #[test]
+    fn resume_keeps_newer_conversation() {
+        let cache = Cache::new();
+        cache.start_conversation(-1, 10, conversation(), Duration::from_secs(60));
+        let (expires_at, old) = cache.take_conversation(-1, 10).unwrap();
+
+        // e.g. the user has called another command meanwhile
+        cache.start_conversation(
+            -1,
+            10,
+            Conversation {
+                command: "weather",
+                state: String::new(),
+            },
+            Duration::from_secs(60),
+        );
+        cache.resume_conversation(-1, 10, expires_at, old);
+
+        assert_eq!(
+            cache.end_conversation(-1, 10).map(|c| c.command),
+            Some("weather")
+        );
+    }
+
+    #[test]
+    fn no_cooldowns_never_block() {
+        let cache = Cache::new();
+
+        assert_eq!(cache.start_cooldowns("weather", &[]), None);
+        assert_eq!(cache.start_cooldowns("weather", &[]), None);
+    }
 }
@@ -0,0 +1,144 @@
+use std::env;
+use std::sync::Arc;
+
+use config::ConfigError;
+use frankenstein::Api;
+
+use crate::cache::Cache;
+use crate::client::Client;
+use crate::commands::CommandsExecutor;
+use crate::services::weather::OpenWeather;
+use crate::settings::{shadowed_text_warning, Settings};
+use crate::updates::RegisterCommands;
+
+// Every key the bot understands, with its default and what it does
+const DEFAULT_CONFIG: &str = include_str!("../config.example.toml");
+
+pub enum Subcommand {
+    // No subcommand, runs the bot
+    Run,
+    CheckConfig,
+    PrintDefaultConfig,
+}
+
+impl Subcommand {
+    pub fn from_args() -> Result<Self, String> {
+        match env::args().nth(1).as_deref() {
+            None => Ok(Self::Run),
+            Some("check-config") => Ok(Self::CheckConfig),
+            Some("print-default-config") => Ok(Self::PrintDefaultConfig),
+            Some(other) => Err(format!(
+                "Unknown subcommand {}, the subcommands are: check-config, print-default-config",
+                other
+            )),
+        }
+    }
+}
+
+// Reads the config the way the bot does on start without connecting to anything,
+// so it can be run before a deploy. Returns the exit code
+pub fn check_config(register_commands: RegisterCommands) -> i32 {
+    check_settings(Settings::load(), register_commands)
+}
+
+// loaded is what Settings::load() returns
+fn check_settings(
+    loaded: (Settings, Vec<ConfigError>),
+    register_commands: RegisterCommands,
+) -> i32 {
+    let (problems, warnings) = find_problems(loaded, register_commands);
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if problems.is_empty() {
+        println!("The config is fine");
+        return 0;
+    }
+
+    eprintln!("Found {} problem(s) in the config:", problems.len());
+    for problem in problems {
+        eprintln!("* {}", problem);
+    }
+
+    1
+}
+
+// The broken keys get their defaults, so the templates and the commands are checked
+// even when some of the keys are wrong. The warnings don't fail the check
+fn find_problems(
+    (mut settings, mut problems): (Settings, Vec<ConfigError>),
+    register_commands: RegisterCommands,
+) -> (Vec<ConfigError>, Vec<String>) {
+    problems.extend(settings.parse_templates());
+    let warnings = settings
+        .shadowed_texts()
+        .iter()
+        .map(|setting| shadowed_text_warning(setting))
+        .collect();
+
+    let api: Arc<dyn Client> = Arc::new(Api::new(settings.token.as_str()));
+    let mut commands_executor = CommandsExecutor::new(
+        Arc::new(settings),
+        api,
+        Arc::new(OpenWeather),
+        Arc::new(Cache::new()),
+    );
+    register_commands(&mut commands_executor);
+    problems.extend(commands_executor.take_init_errors());
+
+    (problems, warnings)
+}
+
+pub fn print_default_config() {
+    print!("{}", DEFAULT_CONFIG);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::load_settings;
+
+    const BROKEN_CONFIG: &str = r#"
+admins = "everyone"
+workers = "many"
+wake_up_format = "{{ username"
+back_from_work_format = "{{ nobody }} is back"
+
+[commands.shuffle]
+cooldown_per_user = "often"
+"#;
+
+    #[test]
+    fn finds_every_problem() {
+        let (problems, _) = find_problems(load_settings(BROKEN_CONFIG), crate::register_commands);
+        let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        for (problem, setting) in problems.iter().zip(&[
+            "`admins`",
+            "`workers`",
+            "wake_up_format setting",
+            "back_from_work_format setting",
+            "[commands.shuffle] settings",
+        ]) {
+            assert!(problem.contains(setting), "{} in {:?}", setting, problem);
+        }
+    }
+
+    #[test]
+    fn broken_config_fails_check() {
+        assert_eq!(
+            check_settings(load_settings(BROKEN_CONFIG), crate::register_commands),
+            1
+        );
+    }
+
+    // The wake_up_format of the tests shadows the bundles, the warnings don't fail the check
+    #[test]
+    fn fine_config_passes_check() {
+        assert_eq!(
+            check_settings(load_settings(""), crate::register_commands),
+            0
+        );
+    }
+}
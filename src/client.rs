@@ -0,0 +1,90 @@
+use frankenstein::{
+    AnswerCallbackQueryParams, AnswerInlineQueryParams, Api, ChatMember, DeleteWebhookParams,
+    Error, GetChatAdministratorsParams, GetUpdatesParams, LeaveChatParams, SendChatActionParams,
+    SendMessageParams, SetMyCommandsParams, SetWebhookParams, TelegramApi, Update, User,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+#[cfg(test)]
+pub mod fake;
+pub mod rate_limited;
+
+pub type ClientResult<T> = Result<T, Error>;
+
+// setWebhook params with secret_token, which frankenstein doesn't support yet. Telegram sends
+// the token back in the X-Telegram-Bot-Api-Secret-Token header of every webhook request
+#[derive(Serialize)]
+pub struct SecretWebhookParams<'a> {
+    #[serde(flatten)]
+    pub params: &'a SetWebhookParams,
+    pub secret_token: &'a str,
+}
+
+// The part of the Telegram Bot API the bot uses. The bot only talks to Telegram through it,
+// so it can run against something other than the real API, e.g. client::fake::RecordingClient.
+pub trait Client: Send + Sync {
+    fn get_me(&self) -> ClientResult<User>;
+    fn get_updates(&self, params: &GetUpdatesParams) -> ClientResult<Vec<Update>>;
+    fn send_message(&self, params: &SendMessageParams) -> ClientResult<()>;
+    fn send_chat_action(&self, params: &SendChatActionParams) -> ClientResult<()>;
+    fn leave_chat(&self, params: &LeaveChatParams) -> ClientResult<()>;
+    fn set_my_commands(&self, params: &SetMyCommandsParams) -> ClientResult<()>;
+    fn set_webhook(&self, params: &SecretWebhookParams) -> ClientResult<()>;
+    fn delete_webhook(&self, params: &DeleteWebhookParams) -> ClientResult<()>;
+    fn answer_callback_query(&self, params: &AnswerCallbackQueryParams) -> ClientResult<()>;
+    fn answer_inline_query(&self, params: &AnswerInlineQueryParams) -> ClientResult<()>;
+    fn get_chat_administrators(
+        &self,
+        params: &GetChatAdministratorsParams,
+    ) -> ClientResult<Vec<ChatMember>>;
+}
+
+impl Client for Api {
+    fn get_me(&self) -> ClientResult<User> {
+        TelegramApi::get_me(self).map(|response| response.result)
+    }
+
+    fn get_updates(&self, params: &GetUpdatesParams) -> ClientResult<Vec<Update>> {
+        TelegramApi::get_updates(self, params).map(|response| response.result)
+    }
+
+    fn send_message(&self, params: &SendMessageParams) -> ClientResult<()> {
+        TelegramApi::send_message(self, params).map(|_| ())
+    }
+
+    fn send_chat_action(&self, params: &SendChatActionParams) -> ClientResult<()> {
+        TelegramApi::send_chat_action(self, params).map(|_| ())
+    }
+
+    fn leave_chat(&self, params: &LeaveChatParams) -> ClientResult<()> {
+        TelegramApi::leave_chat(self, params).map(|_| ())
+    }
+
+    fn set_my_commands(&self, params: &SetMyCommandsParams) -> ClientResult<()> {
+        TelegramApi::set_my_commands(self, params).map(|_| ())
+    }
+
+    fn set_webhook(&self, params: &SecretWebhookParams) -> ClientResult<()> {
+        TelegramApi::request::<_, Value>(self, "setWebhook", Some(params)).map(|_| ())
+    }
+
+    fn delete_webhook(&self, params: &DeleteWebhookParams) -> ClientResult<()> {
+        TelegramApi::delete_webhook(self, params).map(|_| ())
+    }
+
+    fn answer_callback_query(&self, params: &AnswerCallbackQueryParams) -> ClientResult<()> {
+        TelegramApi::answer_callback_query(self, params).map(|_| ())
+    }
+
+    fn answer_inline_query(&self, params: &AnswerInlineQueryParams) -> ClientResult<()> {
+        TelegramApi::answer_inline_query(self, params).map(|_| ())
+    }
+
+    fn get_chat_administrators(
+        &self,
+        params: &GetChatAdministratorsParams,
+    ) -> ClientResult<Vec<ChatMember>> {
+        TelegramApi::get_chat_administrators(self, params).map(|response| response.result)
+    }
+}
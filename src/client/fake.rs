@@ -0,0 +1,205 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use frankenstein::{
+    AnswerCallbackQueryParams, AnswerInlineQueryParams, ChatId, ChatMember, DeleteWebhookParams,
+    Error, GetChatAdministratorsParams, GetUpdatesParams, LeaveChatParams, SendChatActionParams,
+    SendMessageParams, SetMyCommandsParams, Update, User,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::client::{Client, ClientResult, SecretWebhookParams};
+
+// A single call to the API, params are kept as JSON so the calls can be compared
+// regardless of the params type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub method: &'static str,
+    pub params: Value,
+}
+
+// In-process Client which records every call instead of sending it to Telegram
+pub struct RecordingClient {
+    username: String,
+    calls: Mutex<Vec<Call>>,
+    updates: Mutex<VecDeque<Vec<Update>>>,
+    // (method, error) the next calls to the method fail with
+    failures: Mutex<VecDeque<(&'static str, Error)>>,
+    // chat id -> the user ids getChatAdministrators returns
+    chat_admins: Mutex<HashMap<i64, Vec<u64>>>,
+}
+
+impl RecordingClient {
+    pub fn new(username: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            calls: Mutex::new(Vec::new()),
+            updates: Mutex::new(VecDeque::new()),
+            failures: Mutex::new(VecDeque::new()),
+            chat_admins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Queues a batch of updates to be returned by the next get_updates call
+    pub fn push_updates(&self, updates: Vec<Update>) {
+        self.updates.lock().unwrap().push_back(updates);
+    }
+
+    // Makes the next call to the method fail, the call is recorded anyway
+    pub fn fail_next(&self, method: &'static str, error: Error) {
+        self.failures.lock().unwrap().push_back((method, error));
+    }
+
+    // The chats have no admins until they're set
+    pub fn set_chat_admins(&self, chat_id: i64, admins: &[u64]) {
+        self.chat_admins
+            .lock()
+            .unwrap()
+            .insert(chat_id, admins.to_vec());
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn calls_to(&self, method: &str) -> Vec<Value> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| call.method == method)
+            .map(|call| call.params.clone())
+            .collect()
+    }
+
+    // (chat_id, text) of every sent message in the order they were sent
+    pub fn sent_messages(&self) -> Vec<(i64, String)> {
+        self.calls_to("sendMessage")
+            .iter()
+            .map(|params| {
+                (
+                    params["chat_id"].as_i64().unwrap_or_default(),
+                    params["text"].as_str().unwrap_or_default().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    // (chat_id, action) of every sent chat action
+    pub fn chat_actions(&self) -> Vec<(i64, String)> {
+        self.calls_to("sendChatAction")
+            .iter()
+            .map(|params| {
+                (
+                    params["chat_id"].as_i64().unwrap_or_default(),
+                    params["action"].as_str().unwrap_or_default().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn left_chats(&self) -> Vec<i64> {
+        self.calls_to("leaveChat")
+            .iter()
+            .filter_map(|params| params["chat_id"].as_i64())
+            .collect()
+    }
+
+    fn record(&self, method: &'static str, params: &impl Serialize) -> ClientResult<()> {
+        self.calls.lock().unwrap().push(Call {
+            method,
+            params: serde_json::to_value(params).expect("Failed to serialize the params"),
+        });
+
+        let mut failures = self.failures.lock().unwrap();
+        match failures.iter().position(|(failing, _)| *failing == method) {
+            Some(position) => Err(failures.remove(position).unwrap().1),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Client for RecordingClient {
+    fn get_me(&self) -> ClientResult<User> {
+        Ok(serde_json::from_value(json!({
+            "id": 1,
+            "is_bot": true,
+            "first_name": "Maldness Bot",
+            "username": self.username,
+        }))
+        .expect("Failed to build the bot user"))
+    }
+
+    fn get_updates(&self, params: &GetUpdatesParams) -> ClientResult<Vec<Update>> {
+        self.record("getUpdates", params)?;
+        Ok(self.updates.lock().unwrap().pop_front().unwrap_or_default())
+    }
+
+    fn send_message(&self, params: &SendMessageParams) -> ClientResult<()> {
+        self.record("sendMessage", params)
+    }
+
+    fn send_chat_action(&self, params: &SendChatActionParams) -> ClientResult<()> {
+        self.record("sendChatAction", params)
+    }
+
+    fn leave_chat(&self, params: &LeaveChatParams) -> ClientResult<()> {
+        self.record("leaveChat", params)
+    }
+
+    fn set_my_commands(&self, params: &SetMyCommandsParams) -> ClientResult<()> {
+        self.record("setMyCommands", params)
+    }
+
+    fn set_webhook(&self, params: &SecretWebhookParams) -> ClientResult<()> {
+        self.record("setWebhook", params)
+    }
+
+    fn delete_webhook(&self, params: &DeleteWebhookParams) -> ClientResult<()> {
+        self.record("deleteWebhook", params)
+    }
+
+    fn answer_callback_query(&self, params: &AnswerCallbackQueryParams) -> ClientResult<()> {
+        self.record("answerCallbackQuery", params)
+    }
+
+    fn answer_inline_query(&self, params: &AnswerInlineQueryParams) -> ClientResult<()> {
+        self.record("answerInlineQuery", params)
+    }
+
+    fn get_chat_administrators(
+        &self,
+        params: &GetChatAdministratorsParams,
+    ) -> ClientResult<Vec<ChatMember>> {
+        self.record("getChatAdministrators", params)?;
+
+        let chat_id = match params.chat_id {
+            ChatId::Integer(chat_id) => chat_id,
+            ChatId::String(_) => return Ok(Vec::new()),
+        };
+        let admins = self.chat_admins.lock().unwrap();
+        Ok(admins
+            .get(&chat_id)
+            .into_iter()
+            .flatten()
+            .map(|id| {
+                serde_json::from_value(json!({
+                    "status": "administrator",
+                    "user": { "id": id, "is_bot": false, "first_name": "Admin" },
+                    "can_be_edited": false,
+                    "is_anonymous": false,
+                    "can_manage_chat": true,
+                    "can_delete_messages": true,
+                    "can_manage_voice_chats": true,
+                    "can_restrict_members": true,
+                    "can_promote_members": false,
+                    "can_change_info": true,
+                    "can_invite_users": true,
+                    "can_pin_messages": true,
+                }))
+                .expect("Failed to build the chat admin")
+            })
+            .collect())
+    }
+}
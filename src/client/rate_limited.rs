@@ -0,0 +1,477 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use frankenstein::{
+    AnswerCallbackQueryParams, AnswerInlineQueryParams, ChatId, ChatMember, DeleteWebhookParams,
+    Error, GetChatAdministratorsParams, GetUpdatesParams, LeaveChatParams, SendChatActionParams,
+    SendMessageParams, SetMyCommandsParams, Update, User,
+};
+use tracing::{debug, warn};
+
+use crate::client::{Client, ClientResult, SecretWebhookParams};
+use crate::metrics;
+use crate::settings::RateLimitSettings;
+
+const TOO_MANY_REQUESTS: u64 = 429;
+// Chats which haven't received anything for a while are forgotten once there are this many
+const MAX_TRACKED_CHATS: usize = 1000;
+
+struct Window {
+    limit: usize,
+    period: Duration,
+}
+
+impl Window {
+    fn new(limit: usize, period: Duration) -> Self {
+        Self {
+            limit: limit.max(1),
+            period,
+        }
+    }
+
+    // Forgets the messages sent before the window and returns how long to wait for a free slot
+    fn wait_time(&self, sent: &mut VecDeque<Instant>, now: Instant) -> Duration {
+        while let Some(sent_at) = sent.front() {
+            if now.duration_since(*sent_at) < self.period {
+                break;
+            }
+            sent.pop_front();
+        }
+
+        match sent.front() {
+            Some(sent_at) if sent.len() >= self.limit => self.period - now.duration_since(*sent_at),
+            _ => Duration::from_secs(0),
+        }
+    }
+}
+
+// What a request has to wait for
+#[derive(Clone, Copy, Debug)]
+enum Limit {
+    // The global window only, e.g. answers to inline queries
+    Global,
+    // Also the retry_after of the chat, without taking a slot of its window, e.g. chat actions
+    Chat(i64),
+    // Messages count against the window of their chat too
+    Message(i64),
+}
+
+impl Limit {
+    fn chat_id(self) -> Option<i64> {
+        match self {
+            Limit::Global => None,
+            Limit::Chat(chat_id) | Limit::Message(chat_id) => Some(chat_id),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    global: VecDeque<Instant>,
+    chats: HashMap<i64, VecDeque<Instant>>,
+    retry_after: HashMap<i64, Instant>,
+    global_retry_after: Option<Instant>,
+}
+
+// Makes the requests wait for their turn instead of hitting the Telegram limits:
+// https://core.telegram.org/bots/faq#my-bot-is-hitting-limits-how-do-i-avoid-this
+// If Telegram responds with 429 anyway, the request is sent again after retry_after.
+// The worker sending the request is the one waiting, so the updates of the chats assigned to it
+// wait as well.
+pub struct RateLimitedClient<C: Client> {
+    inner: C,
+    global: Window,
+    private: Window,
+    group: Window,
+    max_retries: u32,
+    state: Mutex<State>,
+}
+
+impl<C: Client> RateLimitedClient<C> {
+    pub fn new(inner: C, settings: &RateLimitSettings) -> Self {
+        Self {
+            inner,
+            global: Window::new(settings.global_per_second(), Duration::from_secs(1)),
+            private: Window::new(settings.private_per_second(), Duration::from_secs(1)),
+            group: Window::new(settings.group_per_minute(), Duration::from_secs(60)),
+            max_retries: settings.max_retries(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn chat_window(&self, chat_id: i64) -> &Window {
+        // Group and supergroup ids are negative
+        match chat_id < 0 {
+            true => &self.group,
+            false => &self.private,
+        }
+    }
+
+    // How long to wait before the request can be sent, takes the slots if it can be sent now
+    fn try_acquire(&self, limit: Limit, now: Instant) -> Duration {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        let mut wait = self.global.wait_time(&mut state.global, now);
+        if let Some(until) = state.global_retry_after {
+            wait = wait.max(until.saturating_duration_since(now));
+        }
+        if let Some(until) = limit.chat_id().and_then(|id| state.retry_after.get(&id)) {
+            wait = wait.max(until.saturating_duration_since(now));
+        }
+        if let Limit::Message(chat_id) = limit {
+            let sent = state.chats.entry(chat_id).or_default();
+            wait = wait.max(self.chat_window(chat_id).wait_time(sent, now));
+        }
+
+        if wait > Duration::from_secs(0) {
+            return wait;
+        }
+
+        state.global.push_back(now);
+        state.global_retry_after = None;
+        if let Some(chat_id) = limit.chat_id() {
+            state.retry_after.remove(&chat_id);
+        }
+        if let Limit::Message(chat_id) = limit {
+            state.chats.entry(chat_id).or_default().push_back(now);
+        }
+        if state.chats.len() > MAX_TRACKED_CHATS {
+            state.chats.retain(|_, sent| {
+                sent.back().map_or(false, |sent_at| {
+                    now.duration_since(*sent_at) < Duration::from_secs(60)
+                })
+            });
+        }
+
+        wait
+    }
+
+    fn acquire(&self, limit: Limit) {
+        metrics::OUTGOING_QUEUE_DEPTH.inc();
+
+        loop {
+            let wait = self.try_acquire(limit, Instant::now());
+            if wait == Duration::from_secs(0) {
+                break;
+            }
+
+            debug!(
+                "Rate limited: waiting {:?} to send {:?}, {} request(s) in the queue",
+                wait,
+                limit,
+                metrics::OUTGOING_QUEUE_DEPTH.get()
+            );
+            sleep(wait);
+        }
+
+        metrics::OUTGOING_QUEUE_DEPTH.dec();
+    }
+
+    fn block(&self, limit: Limit, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut state = self.state.lock().unwrap();
+
+        match limit.chat_id() {
+            Some(chat_id) => {
+                state.retry_after.insert(chat_id, until);
+            }
+            // Without a chat id there's nothing to block but everything
+            None => state.global_retry_after = Some(until),
+        }
+    }
+
+    fn send<P, R>(
+        &self,
+        limit: Limit,
+        params: &P,
+        send: impl Fn(&C, &P) -> ClientResult<R>,
+    ) -> ClientResult<R> {
+        let mut attempt = 0;
+
+        loop {
+            self.acquire(limit);
+
+            match send(&self.inner, params) {
+                Err(err) => match retry_after(&err) {
+                    Some(delay) if attempt < self.max_retries => {
+                        attempt += 1;
+                        warn!(
+                            "Telegram asked to retry in {:?} ({:?}, attempt {})",
+                            delay, limit, attempt
+                        );
+                        self.block(limit, delay);
+                    }
+                    _ => return Err(err),
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+// Channel usernames aren't tracked, they only share the global window
+fn chat_limit(chat_id: &ChatId, limit: fn(i64) -> Limit) -> Limit {
+    match chat_id {
+        ChatId::Integer(chat_id) => limit(*chat_id),
+        ChatId::String(_) => Limit::Global,
+    }
+}
+
+fn retry_after(err: &Error) -> Option<Duration> {
+    match err {
+        Error::ApiError(err) if err.error_code == TOO_MANY_REQUESTS => {
+            let seconds = err
+                .parameters
+                .as_ref()
+                .and_then(|parameters| parameters.retry_after)
+                .unwrap_or(1);
+
+            Some(Duration::from_secs(seconds as u64))
+        }
+        _ => None,
+    }
+}
+
+impl<C: Client> Client for RateLimitedClient<C> {
+    fn get_me(&self) -> ClientResult<User> {
+        self.inner.get_me()
+    }
+
+    fn get_updates(&self, params: &GetUpdatesParams) -> ClientResult<Vec<Update>> {
+        self.inner.get_updates(params)
+    }
+
+    fn send_message(&self, params: &SendMessageParams) -> ClientResult<()> {
+        let limit = chat_limit(&params.chat_id, Limit::Message);
+        self.send(limit, params, |inner, params| inner.send_message(params))
+    }
+
+    fn send_chat_action(&self, params: &SendChatActionParams) -> ClientResult<()> {
+        let limit = chat_limit(&params.chat_id, Limit::Chat);
+        self.send(limit, params, |inner, params| {
+            inner.send_chat_action(params)
+        })
+    }
+
+    fn leave_chat(&self, params: &LeaveChatParams) -> ClientResult<()> {
+        let limit = chat_limit(&params.chat_id, Limit::Chat);
+        self.send(limit, params, |inner, params| inner.leave_chat(params))
+    }
+
+    fn set_my_commands(&self, params: &SetMyCommandsParams) -> ClientResult<()> {
+        self.send(Limit::Global, params, |inner, params| {
+            inner.set_my_commands(params)
+        })
+    }
+
+    fn set_webhook(&self, params: &SecretWebhookParams) -> ClientResult<()> {
+        self.inner.set_webhook(params)
+    }
+
+    fn delete_webhook(&self, params: &DeleteWebhookParams) -> ClientResult<()> {
+        self.inner.delete_webhook(params)
+    }
+
+    fn answer_callback_query(&self, params: &AnswerCallbackQueryParams) -> ClientResult<()> {
+        self.send(Limit::Global, params, |inner, params| {
+            inner.answer_callback_query(params)
+        })
+    }
+
+    fn answer_inline_query(&self, params: &AnswerInlineQueryParams) -> ClientResult<()> {
+        self.send(Limit::Global, params, |inner, params| {
+            inner.answer_inline_query(params)
+        })
+    }
+
+    fn get_chat_administrators(
+        &self,
+        params: &GetChatAdministratorsParams,
+    ) -> ClientResult<Vec<ChatMember>> {
+        let limit = chat_limit(&params.chat_id, Limit::Chat);
+        self.send(limit, params, |inner, params| {
+            inner.get_chat_administrators(params)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frankenstein::{ChatAction, ErrorResponse};
+    use serde_json::json;
+
+    use super::*;
+    use crate::client::fake::RecordingClient;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn client() -> RateLimitedClient<RecordingClient> {
+        RateLimitedClient::new(RecordingClient::new("bot"), &RateLimitSettings::default())
+    }
+
+    fn too_many_requests(retry_after: u64) -> Error {
+        Error::ApiError(
+            serde_json::from_value::<ErrorResponse>(json!({
+                "ok": false,
+                "error_code": 429,
+                "description": "Too Many Requests",
+                "parameters": { "retry_after": retry_after },
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn message(chat_id: i64) -> SendMessageParams {
+        SendMessageParams::new(ChatId::Integer(chat_id), "hi".into())
+    }
+
+    #[test]
+    fn window_allows_up_to_limit() {
+        let window = Window::new(2, SECOND);
+        let now = Instant::now();
+        let mut sent = VecDeque::new();
+
+        assert_eq!(window.wait_time(&mut sent, now), Duration::from_secs(0));
+        sent.push_back(now);
+        assert_eq!(window.wait_time(&mut sent, now), Duration::from_secs(0));
+        sent.push_back(now);
+        assert_eq!(window.wait_time(&mut sent, now), SECOND);
+    }
+
+    #[test]
+    fn window_waits_for_oldest_to_expire() {
+        let window = Window::new(2, SECOND);
+        let start = Instant::now();
+        let mut sent = VecDeque::from(vec![start, start + Duration::from_millis(100)]);
+
+        let wait = window.wait_time(&mut sent, start + Duration::from_millis(300));
+
+        assert_eq!(wait, Duration::from_millis(700));
+        assert_eq!(sent.len(), 2);
+    }
+
+    #[test]
+    fn window_forgets_expired() {
+        let window = Window::new(2, SECOND);
+        let start = Instant::now();
+        let mut sent = VecDeque::from(vec![start, start + Duration::from_millis(100)]);
+
+        let wait = window.wait_time(&mut sent, start + SECOND);
+
+        assert_eq!(wait, Duration::from_secs(0));
+        assert_eq!(
+            sent,
+            VecDeque::from(vec![start + Duration::from_millis(100)])
+        );
+    }
+
+    #[test]
+    fn window_limit_is_at_least_one() {
+        let window = Window::new(0, SECOND);
+        let now = Instant::now();
+        let mut sent = VecDeque::new();
+
+        assert_eq!(window.wait_time(&mut sent, now), Duration::from_secs(0));
+        sent.push_back(now);
+        assert_eq!(window.wait_time(&mut sent, now), SECOND);
+    }
+
+    #[test]
+    fn messages_take_chat_slots() {
+        let client = client();
+        let now = Instant::now();
+
+        assert_eq!(
+            client.try_acquire(Limit::Message(1), now),
+            Duration::from_secs(0)
+        );
+        // One message per second in private chats
+        assert_eq!(client.try_acquire(Limit::Message(1), now), SECOND);
+        // Other chats and requests which aren't messages don't wait
+        assert_eq!(
+            client.try_acquire(Limit::Message(2), now),
+            Duration::from_secs(0)
+        );
+        assert_eq!(
+            client.try_acquire(Limit::Chat(1), now),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn global_window_covers_every_request() {
+        let client = client();
+        let now = Instant::now();
+
+        for chat_id in 1..=30 {
+            assert_eq!(
+                client.try_acquire(Limit::Chat(chat_id), now),
+                Duration::from_secs(0)
+            );
+        }
+
+        assert_eq!(client.try_acquire(Limit::Global, now), SECOND);
+        assert_eq!(client.try_acquire(Limit::Message(31), now), SECOND);
+    }
+
+    #[test]
+    fn retry_after_blocks_chat() {
+        let client = client();
+        client.block(Limit::Message(1), Duration::from_secs(5));
+        let now = Instant::now();
+
+        assert!(client.try_acquire(Limit::Chat(1), now) > Duration::from_secs(4));
+        assert_eq!(
+            client.try_acquire(Limit::Chat(2), now),
+            Duration::from_secs(0)
+        );
+        assert_eq!(
+            client.try_acquire(Limit::Chat(1), now + Duration::from_secs(5)),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn retries_on_too_many_requests() {
+        let client = client();
+        client.inner.fail_next("sendMessage", too_many_requests(0));
+
+        assert!(client.send_message(&message(-1)).is_ok());
+        assert_eq!(client.inner.calls_to("sendMessage").len(), 2);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let client = client();
+        for _ in 0..=client.max_retries {
+            client.inner.fail_next("sendMessage", too_many_requests(0));
+        }
+
+        assert!(client.send_message(&message(-1)).is_err());
+        assert_eq!(
+            client.inner.calls_to("sendMessage").len(),
+            client.max_retries as usize + 1
+        );
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        let client = client();
+        let error = serde_json::from_value::<ErrorResponse>(json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: chat not found",
+        }))
+        .unwrap();
+        client
+            .inner
+            .fail_next("sendChatAction", Error::ApiError(error));
+
+        let params = SendChatActionParams::new(ChatId::Integer(1), ChatAction::Typing);
+        assert!(client.send_chat_action(&params).is_err());
+        assert_eq!(client.inner.calls_to("sendChatAction").len(), 1);
+    }
+}
@@ -1,17 +1,45 @@
-use crate::cache::Cache;
+use crate::cache::{Cache, Conversation};
+use crate::client::{Client, ClientResult};
+use crate::commands::args::{Arg, Args};
+use crate::commands::role::Role;
 use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::i18n;
+use crate::i18n::Locale;
+use crate::metrics;
+use crate::middleware::{CommandContext, Middlewares, UpdateContext};
+use crate::services::disabled_command::functions::get_disabled_commands;
+use crate::services::user_role::functions::has_role;
+use crate::services::weather::WeatherClient;
 use crate::settings::Settings;
+use config::ConfigError;
 use diesel::PgConnection;
 use frankenstein::{
-    Api, BotCommand, ChatAction, ChatId, Message, SendChatActionParams, TelegramApi, Update,
+    AnswerCallbackQueryParams, BotCommand, BotCommandScope, BotCommandScopeAllChatAdministrators,
+    BotCommandScopeChat, BotCommandScopeChatAdministrators, BotCommandScopeDefault, CallbackQuery,
+    ChatAction, ChatId, ChatMember, GetChatAdministratorsParams, Message, SendChatActionParams,
+    SetMyCommandsParams,
 };
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn, Span};
 
+pub mod allow_chat;
+pub mod args;
+pub mod cancel;
 pub mod donate;
+pub mod enable;
 pub mod gn;
+pub mod grant;
+pub mod help;
+pub mod language;
+pub mod list_commands;
 pub mod rafk;
+pub mod role;
 pub mod set_my_location;
 pub mod set_paying_status;
+pub mod set_text;
 pub mod shuffle;
 pub mod up;
 pub mod weather;
@@ -19,75 +47,471 @@ pub mod work;
 
 pub type CommandResult<T> = Result<(), T>;
 pub struct CommandParams<'a> {
-    api: &'a Api,
+    api: &'a dyn Client,
+    weather: &'a dyn WeatherClient,
     conn: &'a mut PgConnection,
     cache: &'a Cache,
     settings: &'a Settings,
     message: &'a Message,
-    args: &'a str,
+    args: &'a Args,
+    locale: &'a Locale,
+    executor: &'a CommandsExecutor,
 }
 
-pub struct Command {
-    pub name: &'static str,
-    pub description: &'static str,
-    // The lifetime of CommandParams is the lifetime of CommandsExecutor.execute (e.g. 'execute)
-    // We can't write it like type Handler<'execute> = fn(CommandParams<'execute>) -> ... because
-    // we don't know about the 'execute lifetime at that point.
-    // So instead we use higher-rank trait bounds: https://doc.rust-lang.org/nomicon/hrtb.html
-    pub handler: for<'a> fn(CommandParams<'a>) -> CommandResult<HandleUpdateError>,
-    pub is_admin_only: bool,
-    pub chat_action: Option<ChatAction>,
+pub struct AnswerParams<'a> {
+    api: &'a dyn Client,
+    conn: &'a mut PgConnection,
+    cache: &'a Cache,
+    settings: &'a Settings,
+    message: &'a Message,
+    state: &'a str,
+    // Sent in a private chat or as a reply to the bot, otherwise the message might be meant
+    // for someone else
+    addressed: bool,
+    locale: &'a Locale,
+    executor: &'a CommandsExecutor,
 }
 
-pub struct CommandsExecutor<'a> {
-    settings: &'a Settings,
-    tg_api: &'a Api,
-    commands: HashMap<String, Command>,
+pub struct CallbackParams<'a> {
+    api: &'a dyn Client,
+    conn: &'a mut PgConnection,
     cache: &'a Cache,
+    settings: &'a Settings,
+    query: &'a CallbackQuery,
+    data: &'a str,
+    locale: &'a Locale,
+}
+
+// A command keeps whatever it needs between the calls, e.g. its settings, and is shared between
+// the workers, so the state has to be thread-safe
+pub trait Command: Send + Sync {
+    // Builds the command from the settings, its own section is [commands.<name>]:
+    // settings.command_settings(name)
+    fn init(settings: &Settings) -> Result<Self, ConfigError>
+    where
+        Self: Sized;
+
+    fn name(&self) -> &'static str;
+
+    // In English, the translations are in the bundles: [descriptions] <name> = "..."
+    fn description(&self) -> &'static str;
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError>;
+
+    fn role(&self) -> Role {
+        Role::Everyone
+    }
+
+    fn chat_action(&self) -> Option<ChatAction> {
+        None
+    }
+
+    // Parsed before the command is handled, the usage is sent back if they don't match
+    fn args(&self) -> &'static [Arg] {
+        &[]
+    }
+
+    // Shown in /help <command>
+    fn examples(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn cooldown(&self) -> Cooldown {
+        Cooldown::NONE
+    }
+
+    // Handles the presses of the inline keyboard buttons the command has sent, the buttons'
+    // callback data has to be built with callback_data()
+    fn handle_callback(&self, _params: CallbackParams) -> CommandResult<HandleUpdateError> {
+        Ok(())
+    }
+
+    // Handles the next message of the user the command has asked something with
+    // CommandsExecutor::ask, the conversation is over unless the command asks again.
+    // Returns false if the message isn't an answer, it's handled as usual then and the command
+    // keeps waiting
+    fn handle_answer(&self, _params: AnswerParams) -> Result<bool, HandleUpdateError> {
+        Ok(true)
+    }
+
+    fn usage(&self) -> String {
+        args::usage(self.name(), self.args())
+    }
 }
 
-impl<'a> CommandsExecutor<'a> {
-    pub fn new(settings: &'a Settings, tg_api: &'a Api, cache: &'a Cache) -> Self {
+// How long a command can't be called again after a call, see middleware::cooldown
+#[derive(Clone, Copy)]
+pub struct Cooldown {
+    pub per_user: Option<Duration>,
+    pub per_chat: Option<Duration>,
+}
+
+impl Cooldown {
+    pub const NONE: Self = Self {
+        per_user: None,
+        per_chat: None,
+    };
+}
+
+// A cooldown from the command settings in seconds, 0 turns it off
+pub fn cooldown(seconds: Option<u64>, default: u64) -> Option<Duration> {
+    match seconds.unwrap_or(default) {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    }
+}
+
+const CALLBACK_DATA_SEPARATOR: char = ':';
+
+pub fn callback_data(command: &dyn Command, data: &str) -> String {
+    format!("{}{}{}", command.name(), CALLBACK_DATA_SEPARATOR, data)
+}
+
+// ChatMember is split into a type per status, all of them have the user
+fn chat_member_id(member: &ChatMember) -> u64 {
+    match member {
+        ChatMember::ChatMemberOwner(member) => member.user.id,
+        ChatMember::ChatMemberAdministrator(member) => member.user.id,
+        ChatMember::ChatMemberMember(member) => member.user.id,
+        ChatMember::ChatMemberRestricted(member) => member.user.id,
+        ChatMember::ChatMemberLeft(member) => member.user.id,
+        ChatMember::ChatMemberBanned(member) => member.user.id,
+    }
+}
+
+pub struct CommandsExecutor {
+    settings: Arc<Settings>,
+    tg_api: Arc<dyn Client>,
+    weather: Arc<dyn WeatherClient>,
+    commands: HashMap<String, Box<dyn Command>>,
+    cache: Arc<Cache>,
+    // Every broken command is reported, not only the first one
+    init_errors: Vec<ConfigError>,
+}
+
+impl CommandsExecutor {
+    pub fn new(
+        settings: Arc<Settings>,
+        tg_api: Arc<dyn Client>,
+        weather: Arc<dyn WeatherClient>,
+        cache: Arc<Cache>,
+    ) -> Self {
         Self {
             settings,
             tg_api,
+            weather,
             commands: HashMap::new(),
             cache,
+            init_errors: Vec::new(),
         }
     }
 
-    pub fn bot_commands(&self) -> Vec<BotCommand> {
-        self.commands
-            .values()
-            .map(|cmd| {
-                let mut description = String::new();
-                if cmd.is_admin_only {
-                    description += "[Admin only] ";
-                }
-                description += cmd.description;
+    // The settings the commands were built with
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
 
-                BotCommand::new(cmd.name.to_string(), description)
-            })
-            .collect()
+    pub fn get(&self, name: &str) -> Option<&dyn Command> {
+        self.commands.get(name).map(|cmd| cmd.as_ref())
+    }
+
+    // Sorted by name, so the lists are the same every time
+    pub fn commands(&self) -> Vec<&dyn Command> {
+        let mut commands: Vec<&dyn Command> =
+            self.commands.values().map(|cmd| cmd.as_ref()).collect();
+        commands.sort_by_key(|cmd| cmd.name());
+
+        commands
+    }
+
+    // [command_descriptions] from the settings, then the bundle of the language,
+    // then the one of the command
+    pub fn description(&self, cmd: &dyn Command, language_code: &str) -> String {
+        if let Some(description) = self.settings.command_description(language_code, cmd.name()) {
+            return description.to_string();
+        }
+
+        match i18n::locale(language_code).find(format!("descriptions.{}", cmd.name()).as_str()) {
+            Some(template) => template
+                .render(&liquid::Object::new())
+                .expect("Failed to render a template"),
+            None => cmd.description().to_string(),
+        }
+    }
+
+    // (name, description) of the command
+    fn bot_command(&self, cmd: &dyn Command, language_code: Option<&str>) -> (String, String) {
+        let language_code = language_code.unwrap_or_else(|| self.settings.default_language());
+
+        let mut description = String::new();
+        if let Some(label) = cmd.role().label(i18n::locale(language_code)) {
+            description += label.as_str();
+        }
+        description += self.description(cmd, language_code).as_str();
+
+        (cmd.name().to_string(), description)
     }
 
-    pub fn register(&mut self, command: Command) {
-        if self.commands.contains_key(command.name) {
+    // Sets the commands passing the filter for the scope, for the users with the default
+    // language and for every language with a bundle or translated descriptions. Telegram shows
+    // the list without a language to everyone else, so the languages with the same list are
+    // skipped, unless another list has been published for them before. The lists which haven't
+    // changed since they were published aren't sent again, e.g. on reload.
+    // scope_key names the scope in the cache
+    fn publish_commands(
+        &self,
+        scope: BotCommandScope,
+        scope_key: &str,
+        filter: impl Fn(&dyn Command) -> bool,
+    ) -> ClientResult<()> {
+        let mut language_codes: Vec<&str> = i18n::languages();
+        language_codes.extend(self.settings.command_description_languages());
+        language_codes.sort_unstable();
+        language_codes.dedup();
+
+        let commands = |language_code: Option<&str>| -> Vec<(String, String)> {
+            self.commands()
+                .into_iter()
+                .filter(|cmd| filter(*cmd))
+                .map(|cmd| self.bot_command(cmd, language_code))
+                .collect()
+        };
+        let default_commands = commands(None);
+
+        for language_code in std::iter::once(None).chain(language_codes.into_iter().map(Some)) {
+            let commands = match language_code {
+                Some(_) => commands(language_code),
+                None => default_commands.clone(),
+            };
+
+            let published = self.cache.get_published_commands(scope_key, language_code);
+            let unchanged = match published {
+                Some(published) => published == commands,
+                None => language_code.is_some() && commands == default_commands,
+            };
+            if unchanged {
+                continue;
+            }
+
+            let mut params = SetMyCommandsParams::new(
+                commands
+                    .iter()
+                    .map(|(name, description)| BotCommand::new(name.clone(), description.clone()))
+                    .collect(),
+            );
+            params.set_scope(Some(scope.clone()));
+            params.set_language_code(language_code.map(String::from));
+            self.tg_api.set_my_commands(&params)?;
+
+            self.cache
+                .cache_published_commands(scope_key, language_code, commands);
+        }
+
+        Ok(())
+    }
+
+    fn chat_scope(chat_id: i64) -> (BotCommandScope, String) {
+        (
+            BotCommandScope::BotCommandScopeChat(BotCommandScopeChat::new(
+                "chat".into(),
+                ChatId::Integer(chat_id),
+            )),
+            format!("chat:{}", chat_id),
+        )
+    }
+
+    // Everyone gets the commands anyone can call, chat admins get the chat admin ones too,
+    // and the owners get all of them in their private chats with the bot
+    pub fn send_my_commands(&self) -> ClientResult<()> {
+        self.publish_commands(
+            BotCommandScope::BotCommandScopeDefault(BotCommandScopeDefault::new("default".into())),
+            "default",
+            |cmd| cmd.role() == Role::Everyone,
+        )?;
+        self.publish_commands(
+            BotCommandScope::BotCommandScopeAllChatAdministrators(
+                BotCommandScopeAllChatAdministrators::new("all_chat_administrators".into()),
+            ),
+            "all_chat_administrators",
+            |cmd| cmd.role() != Role::Owner,
+        )?;
+
+        for admin in self.settings.admins.iter() {
+            let (scope, scope_key) = Self::chat_scope(*admin as i64);
+            self.publish_commands(scope, scope_key.as_str(), |_| true)?;
+        }
+
+        Ok(())
+    }
+
+    // Publishes the commands enabled in the chat, so the disabled ones aren't suggested there
+    pub fn send_chat_commands(
+        &self,
+        conn: &mut PgConnection,
+        chat_id: i64,
+    ) -> CommandResult<HandleUpdateError> {
+        let disabled = self.disabled_commands(conn, chat_id)?;
+        let is_enabled = |cmd: &dyn Command| !disabled.iter().any(|name| name == cmd.name());
+
+        // Private chat ids are the user ids
+        let (scope, scope_key) = Self::chat_scope(chat_id);
+        if chat_id > 0 {
+            let is_owner = self.is_admin(chat_id as u64);
+            self.publish_commands(scope, scope_key.as_str(), |cmd| {
+                (is_owner || cmd.role() != Role::Owner) && is_enabled(cmd)
+            })?;
+
+            return Ok(());
+        }
+
+        // The chat scope takes precedence over the all chat administrators one
+        self.publish_commands(scope, scope_key.as_str(), |cmd| {
+            cmd.role() == Role::Everyone && is_enabled(cmd)
+        })?;
+        self.publish_commands(
+            BotCommandScope::BotCommandScopeChatAdministrators(
+                BotCommandScopeChatAdministrators::new(
+                    "chat_administrators".into(),
+                    ChatId::Integer(chat_id),
+                ),
+            ),
+            format!("chat_administrators:{}", chat_id).as_str(),
+            |cmd| cmd.role() != Role::Owner && is_enabled(cmd),
+        )?;
+
+        Ok(())
+    }
+
+    // The commands which fail to init are skipped, see take_init_errors()
+    pub fn register<C: Command + 'static>(&mut self) {
+        let command = match C::init(&self.settings) {
+            Ok(command) => command,
+            Err(err) => {
+                self.init_errors.push(err);
+                return;
+            }
+        };
+        if self.commands.contains_key(command.name()) {
             return;
         }
 
-        self.commands.insert(command.name.to_string(), command);
+        self.commands
+            .insert(command.name().to_string(), Box::new(command));
+    }
+
+    pub fn take_init_errors(&mut self) -> Vec<ConfigError> {
+        std::mem::take(&mut self.init_errors)
     }
 
     fn is_admin(&self, uid: u64) -> bool {
         self.settings.is_admin(uid)
     }
 
+    // The commands disabled in the chat with /disable
+    pub fn disabled_commands(
+        &self,
+        conn: &mut PgConnection,
+        chat_id: i64,
+    ) -> Result<Vec<String>, HandleUpdateError> {
+        if let Some(disabled) = self.cache.get_disabled_commands(chat_id) {
+            return Ok(disabled);
+        }
+
+        let disabled = get_disabled_commands(conn, chat_id)?;
+        self.cache
+            .cache_disabled_commands(chat_id, disabled.clone());
+
+        Ok(disabled)
+    }
+
+    fn is_chat_admin(&self, chat_id: i64, user_id: u64) -> Result<bool, HandleUpdateError> {
+        // Everyone is the admin of their private chat with the bot
+        if chat_id > 0 {
+            return Ok(chat_id == user_id as i64);
+        }
+
+        let ttl = self.settings.roles.chat_admins_cache_time();
+        let admins = match self.cache.get_chat_admins(chat_id, ttl) {
+            Some(admins) => admins,
+            None => {
+                let admins: Vec<u64> = self
+                    .tg_api
+                    .get_chat_administrators(&GetChatAdministratorsParams::new(ChatId::Integer(
+                        chat_id,
+                    )))?
+                    .iter()
+                    .map(chat_member_id)
+                    .collect();
+                self.cache.cache_chat_admins(chat_id, admins.clone(), ttl);
+
+                admins
+            }
+        };
+
+        Ok(admins.contains(&user_id))
+    }
+
+    // chat_id is None for the callback queries from the inline messages,
+    // only the roles which don't depend on the chat are checked then
+    pub fn has_role(
+        &self,
+        conn: &mut PgConnection,
+        chat_id: Option<i64>,
+        user_id: u64,
+        role: Role,
+    ) -> Result<bool, HandleUpdateError> {
+        if role == Role::Everyone || self.is_admin(user_id) {
+            return Ok(true);
+        }
+        let chat_id = match chat_id {
+            Some(chat_id) => chat_id,
+            None => return Ok(false),
+        };
+
+        match role {
+            Role::Everyone => Ok(true),
+            Role::Owner => Ok(false),
+            Role::ChatAdmin => self.is_chat_admin(chat_id, user_id),
+            Role::Custom(name) => Ok(
+                self.is_chat_admin(chat_id, user_id)? || has_role(conn, chat_id, user_id, name)?
+            ),
+        }
+    }
+
+    // Whether the author of the message can call the command
+    pub fn can_call(
+        &self,
+        conn: &mut PgConnection,
+        command: &dyn Command,
+        message: &Message,
+    ) -> Result<bool, HandleUpdateError> {
+        match message.from.as_ref() {
+            Some(from) => self.has_role(conn, Some(message.chat.id), from.id, command.role()),
+            None => Ok(command.role() == Role::Everyone),
+        }
+    }
+
+    // The roles which can be granted with /grant
+    pub fn custom_roles(&self) -> Vec<&'static str> {
+        let mut roles: Vec<&'static str> = self
+            .commands
+            .values()
+            .filter_map(|cmd| match cmd.role() {
+                Role::Custom(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        roles.sort_unstable();
+        roles.dedup();
+
+        roles
+    }
+
     pub fn execute(
         &self,
+        middlewares: &Middlewares,
         bot_prefix: &str,
-        conn: &mut PgConnection,
-        update: &Update,
+        ctx: &mut UpdateContext,
         command_entity: &str,
         message: &Message,
         args: &str,
@@ -103,7 +527,7 @@ impl<'a> CommandsExecutor<'a> {
             command_name = &command_name[..at_index];
 
             if bot_name != bot_prefix {
-                println!(
+                debug!(
                     "My name is {}, but the command was called for {}",
                     bot_prefix, bot_name
                 );
@@ -111,30 +535,498 @@ impl<'a> CommandsExecutor<'a> {
             }
         }
 
-        println!("Command name: {:?}", command_name);
+        Span::current().record("command", &command_name);
+        debug!("Command name: {:?}", command_name);
 
-        if let Some(command) = self.commands.get(command_name) {
-            if let Some(chat_action) = command.chat_action.as_ref() {
+        if let Some(command) = self.get(command_name) {
+            // Only the commands anyone can call can be disabled, otherwise /enable could lock itself out
+            if command.role() == Role::Everyone {
+                match self.disabled_commands(ctx.conn, message.chat.id) {
+                    Ok(disabled) if disabled.iter().any(|name| name == command.name()) => {
+                        debug!("Command is disabled in this chat");
+                        return None;
+                    }
+                    Ok(_) => {}
+                    Err(err) => return Some(err),
+                }
+            }
+            match self.can_call(ctx.conn, command, message) {
+                Ok(true) => {}
+                Ok(false) => {
+                    debug!("Not allowed to call the command");
+                    return None;
+                }
+                Err(err) => return Some(err),
+            }
+            if let Some(chat_action) = command.chat_action() {
                 let _ = self.tg_api.send_chat_action(&SendChatActionParams::new(
                     ChatId::Integer(message.chat.id),
-                    chat_action.clone(),
+                    chat_action,
                 ));
             }
-            if command.is_admin_only && !self.is_admin(update.message.as_ref()?.from.as_ref()?.id) {
-                return None;
-            }
-            return match (command.handler)(CommandParams {
-                api: self.tg_api,
-                conn,
-                cache: self.cache,
-                settings: self.settings,
+            let args = match args::parse(command.args(), args, message) {
+                Ok(args) => args,
+                Err(err) => {
+                    return Some(HandleUpdateError::BadArgument(ctx.locale.render(
+                        "args.usage",
+                        &liquid::object!({
+                            "error": err.text(ctx.locale),
+                            "usage": command.usage(),
+                        }),
+                    )))
+                }
+            };
+            let mut context = CommandContext {
+                api: self.tg_api.as_ref(),
+                conn: &mut *ctx.conn,
+                cache: &self.cache,
+                settings: &self.settings,
+                command,
                 message,
                 args,
-            }) {
-                Ok(_) => None,
-                Err(e) => Some(e),
+                locale: ctx.locale,
+                extensions: &mut ctx.extensions,
             };
+            return middlewares
+                .run_command(&mut context, |ctx| {
+                    metrics::COMMAND_INVOCATIONS
+                        .with_label_values(&[command.name()])
+                        .inc();
+                    command
+                        .handle(CommandParams {
+                            api: ctx.api,
+                            weather: self.weather.as_ref(),
+                            conn: &mut *ctx.conn,
+                            cache: ctx.cache,
+                            settings: ctx.settings,
+                            message: ctx.message,
+                            args: &ctx.args,
+                            locale: ctx.locale,
+                            executor: self,
+                        })
+                        .map_err(|e| {
+                            metrics::COMMAND_FAILURES
+                                .with_label_values(&[command.name()])
+                                .inc();
+                            e
+                        })
+                })
+                .err();
         }
         None
     }
+
+    // Sends the question and passes the next message of the user in this chat to the command's
+    // handle_answer instead of handling it as usual, unless it's a /cancel or the time runs out
+    pub fn ask(
+        &self,
+        command: &dyn Command,
+        message: &Message,
+        state: &str,
+        question: String,
+    ) -> CommandResult<HandleUpdateError> {
+        let from = message
+            .from
+            .as_ref()
+            .ok_or_else(|| HandleUpdateError::Command("from in the message is empty".into()))?;
+
+        helpers::send_text_message(
+            self.tg_api.as_ref(),
+            message.chat.id,
+            question,
+            Some(message.message_id),
+        )?;
+        self.cache.start_conversation(
+            message.chat.id,
+            from.id,
+            Conversation {
+                command: command.name(),
+                state: state.to_string(),
+            },
+            self.settings.conversations.timeout(),
+        );
+
+        Ok(())
+    }
+
+    // Returns false if no command is waiting for the message or the message isn't an answer
+    pub fn answer(
+        &self,
+        conn: &mut PgConnection,
+        bot_username: &str,
+        message: &Message,
+        locale: &Locale,
+    ) -> Result<bool, HandleUpdateError> {
+        let from = match message.from.as_ref() {
+            Some(from) => from,
+            None => return Ok(false),
+        };
+        let (expires_at, conversation) =
+            match self.cache.take_conversation(message.chat.id, from.id) {
+                Some(conversation) => conversation,
+                None => return Ok(false),
+            };
+        let command = match self.get(conversation.command) {
+            Some(command) => command,
+            None => return Ok(false),
+        };
+
+        let is_reply_to_bot = message
+            .reply_to_message
+            .as_ref()
+            .and_then(|reply| reply.from.as_ref())
+            .map_or(false, |author| {
+                author.is_bot && author.username.as_deref() == Some(bot_username)
+            });
+
+        Span::current().record("command", &command.name());
+        debug!("Answer for command: {:?}", command.name());
+
+        let answered = command.handle_answer(AnswerParams {
+            api: self.tg_api.as_ref(),
+            conn,
+            cache: &self.cache,
+            settings: &self.settings,
+            message,
+            state: conversation.state.as_str(),
+            addressed: message.chat.type_field == "private" || is_reply_to_bot,
+            locale,
+            executor: self,
+        })?;
+        if !answered {
+            debug!("Not an answer, the command keeps waiting");
+            self.cache
+                .resume_conversation(message.chat.id, from.id, expires_at, conversation);
+        }
+
+        Ok(answered)
+    }
+
+    pub fn execute_callback(
+        &self,
+        conn: &mut PgConnection,
+        query: &CallbackQuery,
+        locale: &Locale,
+    ) -> Option<HandleUpdateError> {
+        let result = self.handle_callback(conn, query, locale);
+
+        // Telegram shows a loading indicator on the button until the query is answered
+        if let Err(err) = self
+            .tg_api
+            .answer_callback_query(&AnswerCallbackQueryParams::new(query.id.clone()))
+        {
+            warn!("Failed to answer the callback query: {:?}", err);
+        }
+
+        result
+    }
+
+    fn handle_callback(
+        &self,
+        conn: &mut PgConnection,
+        query: &CallbackQuery,
+        locale: &Locale,
+    ) -> Option<HandleUpdateError> {
+        let data = query.data.as_ref()?;
+        let (command_name, data) = data.split_once(CALLBACK_DATA_SEPARATOR)?;
+
+        Span::current().record("command", &command_name);
+        debug!("Callback for command: {:?}", command_name);
+
+        let command = self.get(command_name)?;
+
+        let chat_id = query.message.as_ref().map(|message| message.chat.id);
+        match self.has_role(conn, chat_id, query.from.id, command.role()) {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(err) => return Some(err),
+        }
+
+        metrics::COMMAND_INVOCATIONS
+            .with_label_values(&[command.name()])
+            .inc();
+        match command.handle_callback(CallbackParams {
+            api: self.tg_api.as_ref(),
+            conn,
+            cache: &self.cache,
+            settings: &self.settings,
+            query,
+            data,
+            locale,
+        }) {
+            Ok(_) => None,
+            Err(e) => {
+                metrics::COMMAND_FAILURES
+                    .with_label_values(&[command.name()])
+                    .inc();
+                Some(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::client::fake::RecordingClient;
+    use crate::commands::role;
+    use crate::services::user_role::functions::grant;
+    use crate::services::weather::fake::FakeWeather;
+    use crate::testing::{
+        self, bot_user, callback_query, group, message, random_id, user, TestBot,
+    };
+
+    #[test]
+    fn cooldown_from_settings() {
+        assert_eq!(cooldown(Some(10), 30), Some(Duration::from_secs(10)));
+        assert_eq!(cooldown(None, 30), Some(Duration::from_secs(30)));
+        assert_eq!(cooldown(Some(0), 30), None);
+        assert_eq!(cooldown(None, 0), None);
+    }
+
+    // Builds the commands with the config and publishes them, like a start or a reload does
+    fn publish(config: &str, client: &Arc<RecordingClient>, cache: &Arc<Cache>) {
+        let api: Arc<dyn Client> = Arc::clone(client) as Arc<dyn Client>;
+        let mut executor = CommandsExecutor::new(
+            Arc::new(testing::settings(config)),
+            api,
+            Arc::new(FakeWeather::default()),
+            Arc::clone(cache),
+        );
+        crate::register_commands(&mut executor);
+
+        executor.send_my_commands().unwrap();
+    }
+
+    // (scope, language_code) of every setMyCommands request, e.g. ("chat:2", Some("ru"))
+    fn published(client: &RecordingClient) -> Vec<(String, Option<String>)> {
+        client
+            .calls_to("setMyCommands")
+            .iter()
+            .map(|params| {
+                let scope = params["scope"]["type"].as_str().unwrap();
+                let scope = match params["scope"]["chat_id"].as_i64() {
+                    Some(chat_id) => format!("{}:{}", scope, chat_id),
+                    None => scope.to_string(),
+                };
+
+                (scope, params["language_code"].as_str().map(String::from))
+            })
+            .collect()
+    }
+
+    fn request(scope: &str, language_code: Option<&str>) -> (String, Option<String>) {
+        (scope.to_string(), language_code.map(String::from))
+    }
+
+    #[test]
+    fn unchanged_commands_arent_published_again() {
+        let client = Arc::new(RecordingClient::new(testing::BOT_USERNAME));
+        let cache = Arc::new(Cache::new());
+
+        publish("", &client, &cache);
+        // The en list is the same as the one without a language
+        assert_eq!(
+            published(&client),
+            vec![
+                request("default", None),
+                request("default", Some("ru")),
+                request("all_chat_administrators", None),
+                request("all_chat_administrators", Some("ru")),
+                request("chat:2", None),
+                request("chat:2", Some("ru")),
+            ]
+        );
+
+        publish("", &client, &cache);
+        assert_eq!(published(&client).len(), 6);
+    }
+
+    #[test]
+    fn changed_commands_are_published_per_scope_and_language() {
+        let client = Arc::new(RecordingClient::new(testing::BOT_USERNAME));
+        let cache = Arc::new(Cache::new());
+        publish("", &client, &cache);
+
+        // Only the owners get /allow_chat
+        publish(
+            "[command_descriptions.ru]\nallow_chat = \"Пустить в чат\"",
+            &client,
+            &cache,
+        );
+        assert_eq!(published(&client)[6..], [request("chat:2", Some("ru"))]);
+
+        publish(
+            "[command_descriptions.ru]\nallow_chat = \"Пустить в чат\"\ngn = \"Спать\"",
+            &client,
+            &cache,
+        );
+        assert_eq!(
+            published(&client)[7..],
+            [
+                request("default", Some("ru")),
+                request("all_chat_administrators", Some("ru")),
+                request("chat:2", Some("ru")),
+            ]
+        );
+    }
+
+    const OWNER_ID: u64 = 2;
+
+    #[test]
+    #[ignore]
+    fn roles_include_the_ones_below_them() {
+        let bot = TestBot::new("");
+        let mut conn = bot.connection();
+        let executor = bot.handler.commands_executor();
+        let chat_id = -random_id();
+        let (admin, moderator, member) =
+            (random_id() as u64, random_id() as u64, random_id() as u64);
+        bot.client.set_chat_admins(chat_id, &[admin]);
+        grant(&mut conn, chat_id, moderator, role::MODERATOR.name()).unwrap();
+
+        let mut roles = |chat_id: Option<i64>, user_id: u64| -> Vec<&'static str> {
+            [
+                Role::Everyone,
+                role::MODERATOR,
+                Role::ChatAdmin,
+                Role::Owner,
+            ]
+            .iter()
+            .filter(|role| {
+                executor
+                    .has_role(&mut conn, chat_id, user_id, **role)
+                    .unwrap()
+            })
+            .map(Role::name)
+            .collect()
+        };
+
+        assert_eq!(
+            roles(Some(chat_id), OWNER_ID),
+            vec!["everyone", "moderator", "admin", "owner"]
+        );
+        assert_eq!(
+            roles(Some(chat_id), admin),
+            vec!["everyone", "moderator", "admin"]
+        );
+        assert_eq!(
+            roles(Some(chat_id), moderator),
+            vec!["everyone", "moderator"]
+        );
+        assert_eq!(roles(Some(chat_id), member), vec!["everyone"]);
+        // The custom roles are per chat
+        assert_eq!(roles(Some(-random_id()), moderator), vec!["everyone"]);
+        // Everyone is the admin of their private chat
+        assert_eq!(
+            roles(Some(member as i64), member),
+            vec!["everyone", "moderator", "admin"]
+        );
+        // Without a chat only the owners have the roles
+        assert_eq!(roles(None, admin), vec!["everyone"]);
+        assert_eq!(
+            roles(None, OWNER_ID),
+            vec!["everyone", "moderator", "admin", "owner"]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn chat_admins_are_cached_until_expiry() {
+        let bot = TestBot::new("[roles]\nchat_admins_cache_time = 1");
+        let mut conn = bot.connection();
+        let executor = bot.handler.commands_executor();
+        let chat_id = -random_id();
+        let admin = random_id() as u64;
+        let mut is_admin = || {
+            executor
+                .has_role(&mut conn, Some(chat_id), admin, Role::ChatAdmin)
+                .unwrap()
+        };
+
+        bot.client.set_chat_admins(chat_id, &[admin]);
+        assert!(is_admin());
+        // Demoted, but the cached admins are used until they expire
+        bot.client.set_chat_admins(chat_id, &[]);
+        assert!(is_admin());
+        assert_eq!(bot.client.calls_to("getChatAdministrators").len(), 1);
+
+        thread::sleep(Duration::from_millis(1100));
+        assert!(!is_admin());
+        assert_eq!(bot.client.calls_to("getChatAdministrators").len(), 2);
+    }
+
+    // Sends the data of the pressed buttons to the user who pressed them
+    struct Echo;
+
+    impl Command for Echo {
+        fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+            Ok(Self)
+        }
+
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn description(&self) -> &'static str {
+            "Echo the pressed button"
+        }
+
+        fn role(&self) -> Role {
+            Role::ChatAdmin
+        }
+
+        fn handle(&self, _params: CommandParams) -> CommandResult<HandleUpdateError> {
+            Ok(())
+        }
+
+        fn handle_callback(
+            &self,
+            CallbackParams {
+                api, query, data, ..
+            }: CallbackParams,
+        ) -> CommandResult<HandleUpdateError> {
+            helpers::send_text_message(api, query.from.id as i64, data.to_string(), None)
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn callbacks_are_routed_to_their_command() {
+        let bot = TestBot::new("");
+        let mut conn = bot.connection();
+        let mut executor = CommandsExecutor::new(
+            Arc::new(testing::settings("")),
+            Arc::clone(&bot.client) as Arc<dyn Client>,
+            Arc::clone(&bot.weather) as Arc<dyn WeatherClient>,
+            Arc::clone(&bot.cache),
+        );
+        executor.register::<Echo>();
+        let group_id = random_id();
+        let (admin, member) = (random_id(), random_id());
+        bot.client.set_chat_admins(-group_id, &[admin as u64]);
+
+        let mut press = |from: i64, data: &str| {
+            let query: CallbackQuery = serde_json::from_value(callback_query(
+                &user(from, "alice"),
+                message(1, &group(group_id), &bot_user(), "Press a button"),
+                data,
+            ))
+            .unwrap();
+            executor.execute_callback(&mut conn, &query, i18n::locale("en"))
+        };
+
+        // The data of the command is everything after the first separator
+        assert!(press(admin, "echo:1:2").is_none());
+        assert!(press(admin, "unknown:1").is_none());
+        assert!(press(admin, "echo").is_none());
+        // The role of the command applies to its buttons too
+        assert!(press(member, "echo:3").is_none());
+
+        assert_eq!(bot.client.sent_messages(), vec![(admin, "1:2".to_string())]);
+        // Every button stops loading
+        assert_eq!(bot.client.calls_to("answerCallbackQuery").len(), 4);
+    }
 }
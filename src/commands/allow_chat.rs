@@ -0,0 +1,227 @@
+use config::ConfigError;
+use frankenstein::{
+    ChatId, InlineKeyboardButton, InlineKeyboardMarkup, LeaveChatParams, Message, ReplyMarkup,
+    SendMessageParams,
+};
+use tracing::warn;
+
+use crate::client::Client;
+use crate::commands::args::{Arg, ArgKind};
+use crate::commands::role::Role;
+use crate::commands::{callback_data, CallbackParams, Command, CommandParams, CommandResult};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::i18n;
+use crate::i18n::Locale;
+use crate::services::chat_permission::functions::set_permission;
+use crate::services::chat_permission::ChatPermission;
+use crate::settings::Settings;
+
+// The current chat when omitted
+const ARGS: &[Arg] = &[Arg::optional("chat_id", ArgKind::Integer)];
+
+pub struct AllowChat;
+
+impl Command for AllowChat {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "allow_chat"
+    }
+
+    fn description(&self) -> &'static str {
+        "Let a chat use the bot"
+    }
+
+    fn role(&self) -> Role {
+        Role::Owner
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/allow_chat", "/allow_chat -1001234567890"]
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        decide_by_command(params, ChatPermission::Allowed)
+    }
+
+    // The Approve button of the join request
+    fn handle_callback(&self, params: CallbackParams) -> CommandResult<HandleUpdateError> {
+        decide_by_button(params, ChatPermission::Allowed)
+    }
+}
+
+pub struct DenyChat;
+
+impl Command for DenyChat {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "deny_chat"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stop a chat from using the bot and leave it"
+    }
+
+    fn role(&self) -> Role {
+        Role::Owner
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/deny_chat", "/deny_chat -1001234567890"]
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        decide_by_command(params, ChatPermission::Denied)
+    }
+
+    // The Reject button of the join request
+    fn handle_callback(&self, params: CallbackParams) -> CommandResult<HandleUpdateError> {
+        decide_by_button(params, ChatPermission::Denied)
+    }
+}
+
+fn decide_by_command(
+    CommandParams {
+        api,
+        conn,
+        cache,
+        message,
+        args,
+        locale,
+        ..
+    }: CommandParams,
+    permission: ChatPermission,
+) -> CommandResult<HandleUpdateError> {
+    let chat_id = args.integer("chat_id").unwrap_or(message.chat.id);
+
+    set_permission(conn, chat_id, permission)?;
+    cache.cache_chat_permission(chat_id, Some(permission));
+
+    reply(api, locale, chat_id, permission, message)?;
+    leave_if_denied(api, chat_id, permission);
+
+    Ok(())
+}
+
+// The chat id is the callback data, the answer goes to the owner who has pressed the button
+fn decide_by_button(
+    CallbackParams {
+        api,
+        conn,
+        cache,
+        query,
+        data,
+        locale,
+        ..
+    }: CallbackParams,
+    permission: ChatPermission,
+) -> CommandResult<HandleUpdateError> {
+    let chat_id = data
+        .parse::<i64>()
+        .map_err(|e| HandleUpdateError::Command(format!("Invalid chat id {}: {}", data, e)))?;
+
+    set_permission(conn, chat_id, permission)?;
+    cache.cache_chat_permission(chat_id, Some(permission));
+
+    if let Some(message) = query.message.as_ref() {
+        reply(api, locale, chat_id, permission, message)?;
+    }
+    leave_if_denied(api, chat_id, permission);
+
+    Ok(())
+}
+
+fn reply(
+    api: &dyn Client,
+    locale: &Locale,
+    chat_id: i64,
+    permission: ChatPermission,
+    message: &Message,
+) -> CommandResult<HandleUpdateError> {
+    let key = match permission {
+        ChatPermission::Denied => "allow_chat.denied",
+        _ => "allow_chat.allowed",
+    };
+
+    helpers::send_text_message(
+        api,
+        message.chat.id,
+        locale.render(key, &liquid::object!({ "chat_id": chat_id })),
+        Some(message.message_id),
+    )
+}
+
+// The private chats can't be left, their ids are positive
+fn leave_if_denied(api: &dyn Client, chat_id: i64, permission: ChatPermission) {
+    if permission != ChatPermission::Denied || chat_id > 0 {
+        return;
+    }
+
+    // The bot might not be in the chat anymore
+    if let Err(err) = api
+        .leave_chat(&LeaveChatParams::new(ChatId::Integer(chat_id)))
+        .map_err(HandleUpdateError::from)
+    {
+        warn!("Failed to leave the denied chat {}: {}", chat_id, err);
+    }
+}
+
+// Asks the owners whether the bot should stay in a group [allowed_chats] doesn't know,
+// the buttons work like /allow_chat and /deny_chat. The owners' languages are unknown,
+// so the request is in the default one
+pub fn send_join_request(api: &dyn Client, settings: &Settings, message: &Message) {
+    let chat_id = message.chat.id;
+    let locale = i18n::locale(settings.default_language());
+    let user = message
+        .from
+        .as_ref()
+        .map(|from| match from.username.as_ref() {
+            Some(username) => format!("@{}", username),
+            None => from.first_name.clone(),
+        });
+    let text = locale.render(
+        "allow_chat.join_request",
+        &liquid::object!({
+            "chat": message.chat.title.clone().unwrap_or_default(),
+            "chat_id": chat_id,
+            "user": user.unwrap_or_default(),
+        }),
+    );
+
+    let button = |key: &str, command: &dyn Command| {
+        let mut button = InlineKeyboardButton::new(locale.text(key));
+        button.set_callback_data(Some(callback_data(command, chat_id.to_string().as_str())));
+        button
+    };
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        button("allow_chat.approve", &AllowChat),
+        button("allow_chat.reject", &DenyChat),
+    ]]);
+
+    // Private chat ids are the user ids
+    for admin in settings.admins.iter() {
+        let mut params = SendMessageParams::new(ChatId::Integer(*admin as i64), text.clone());
+        params.set_reply_markup(Some(ReplyMarkup::InlineKeyboardMarkup(keyboard.clone())));
+
+        if let Err(err) = api.send_message(&params).map_err(HandleUpdateError::from) {
+            warn!(
+                "Failed to send the join request to admin {}: {}",
+                admin, err
+            );
+        }
+    }
+}
@@ -0,0 +1,716 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use frankenstein::{Message, User};
+
+use crate::i18n::Locale;
+
+const TEXT_MENTION: &str = "text_mention";
+const FLAG_PREFIX: &str = "--";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    // A single word or a "quoted string"
+    Word,
+    // The rest of the arguments as is, has to be the last one. The flags go before it,
+    // a flag after the start of the text is an error
+    Text,
+    Integer,
+    Bool,
+    // Human-readable duration, e.g. 1h30m
+    Duration,
+    // @username or a mention of a user without a username,
+    // falls back to the author of the replied message when omitted
+    User,
+    // --name, either present or not
+    Flag,
+}
+
+// Declarative description of a command argument, the arguments are parsed
+// in the declaration order before the handler is called
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+impl Arg {
+    pub const fn required(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub const fn optional(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: true,
+        }
+    }
+
+    pub const fn flag(name: &'static str) -> Self {
+        Self::optional(name, ArgKind::Flag)
+    }
+
+    fn usage(&self) -> String {
+        let name = match self.kind {
+            ArgKind::Flag => format!("{}{}", FLAG_PREFIX, self.name),
+            ArgKind::Text => format!("{}...", self.name),
+            _ => self.name.to_string(),
+        };
+
+        match self.optional {
+            true => format!("[{}]", name),
+            false => format!("<{}>", name),
+        }
+    }
+}
+
+pub enum Mention {
+    Username(String),
+    User(User),
+}
+
+enum Value {
+    Text(String),
+    Integer(i64),
+    Bool(bool),
+    Duration(Duration),
+    User(Mention),
+}
+
+#[derive(Default)]
+pub struct Args {
+    values: HashMap<&'static str, Value>,
+    flags: HashSet<&'static str>,
+}
+
+// Required arguments are always present once the arguments are parsed,
+// so the accessors only return None for the missing optional ones
+impl Args {
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.values.get(name)? {
+            Value::Text(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.values.get(name)? {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.values.get(name)? {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self, name: &str) -> Option<Duration> {
+        match self.values.get(name)? {
+            Value::Duration(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn user(&self, name: &str) -> Option<&Mention> {
+        match self.values.get(name)? {
+            Value::User(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+}
+
+#[derive(Debug)]
+pub enum ArgsError {
+    Missing(&'static str),
+    Invalid {
+        name: &'static str,
+        value: String,
+        // The [args] text describing the expected value, e.g. "integer"
+        expected: &'static str,
+    },
+    Unexpected(String),
+    UnclosedQuote,
+    // A flag after the start of ArgKind::Text
+    MisplacedFlag(&'static str),
+}
+
+impl ArgsError {
+    // Explains the error to the user in their language
+    pub fn text(&self, locale: &Locale) -> String {
+        match *self {
+            Self::Missing(name) => {
+                locale.render("args.missing", &liquid::object!({ "name": name }))
+            }
+            Self::Invalid {
+                name,
+                ref value,
+                expected,
+            } => locale.render(
+                "args.invalid",
+                &liquid::object!({
+                    "name": name,
+                    "value": value,
+                    "expected": locale.text(format!("args.{}", expected).as_str()),
+                }),
+            ),
+            Self::Unexpected(ref value) => {
+                locale.render("args.unexpected", &liquid::object!({ "value": value }))
+            }
+            Self::UnclosedQuote => locale.text("args.unclosed_quote"),
+            Self::MisplacedFlag(name) => {
+                locale.render("args.misplaced_flag", &liquid::object!({ "name": name }))
+            }
+        }
+    }
+}
+
+struct Token<'a> {
+    value: String,
+    // The arguments starting with this token, used by ArgKind::Text
+    rest: &'a str,
+    quoted: bool,
+    // Byte range in the arguments, the quotes included
+    start: usize,
+    end: usize,
+}
+
+fn is_quote(c: char) -> bool {
+    // Phones tend to replace the straight quotes with the curly ones
+    matches!(c, '"' | '“' | '”')
+}
+
+fn tokenize(args: &str) -> Result<Vec<Token>, ArgsError> {
+    let mut tokens = Vec::new();
+    let mut chars = args.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        let mut value = String::new();
+        let quoted = is_quote(c);
+        let end = if quoted {
+            loop {
+                match chars.next() {
+                    Some((i, c)) if is_quote(c) => break i + c.len_utf8(),
+                    Some((_, c)) => value.push(c),
+                    None => return Err(ArgsError::UnclosedQuote),
+                }
+            }
+        } else {
+            value.push(c);
+            while let Some((_, c)) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                value.push(*c);
+                chars.next();
+            }
+            start + value.len()
+        };
+
+        tokens.push(Token {
+            value,
+            rest: &args[start..],
+            quoted,
+            start,
+            end,
+        });
+    }
+
+    Ok(tokens)
+}
+
+// A mention of a user without a username, the name might be several words, e.g. John Smith
+struct TextMention<'a> {
+    // Byte range in the arguments
+    start: usize,
+    end: usize,
+    user: &'a User,
+}
+
+// Mentions of users without a username are only visible in the message entities. Their offsets
+// are in UTF-16 code units of the whole text, the arguments are the end of the text
+fn text_mentions<'a>(message: &'a Message, args: &str) -> Vec<TextMention<'a>> {
+    let text = match message.text.as_ref() {
+        Some(text) if text.ends_with(args) => text,
+        _ => return Vec::new(),
+    };
+    let args_start = text.len() - args.len();
+
+    // The byte offset of every UTF-16 offset
+    let mut byte_offsets: Vec<usize> = text
+        .char_indices()
+        .flat_map(|(i, c)| std::iter::repeat(i).take(c.len_utf16()))
+        .collect();
+    byte_offsets.push(text.len());
+    let byte_offset = |offset: usize| byte_offsets.get(offset).copied();
+
+    message
+        .entities
+        .iter()
+        .flatten()
+        .filter(|entity| entity.type_field.as_str() == TEXT_MENTION)
+        .filter_map(|entity| {
+            let start = byte_offset(entity.offset as usize)?;
+            let end =
+                byte_offset(entity.offset as usize + entity.length as usize).unwrap_or(text.len());
+
+            Some(TextMention {
+                start: start.checked_sub(args_start)?,
+                end: end - args_start,
+                user: entity.user.as_ref()?,
+            })
+        })
+        .collect()
+}
+
+fn parse_value(arg: &Arg, token: &Token) -> Result<Value, ArgsError> {
+    let invalid = |expected| ArgsError::Invalid {
+        name: arg.name,
+        value: token.value.clone(),
+        expected,
+    };
+
+    match arg.kind {
+        ArgKind::Word => Ok(Value::Text(token.value.clone())),
+        ArgKind::Text => Ok(Value::Text(token.rest.trim_end().to_string())),
+        ArgKind::Integer => token
+            .value
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| invalid("integer")),
+        ArgKind::Bool => match token.value.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(Value::Bool(true)),
+            "false" | "no" | "off" | "0" => Ok(Value::Bool(false)),
+            _ => Err(invalid("bool")),
+        },
+        ArgKind::Duration => humantime::parse_duration(token.value.as_str())
+            .map(Value::Duration)
+            .map_err(|_| invalid("duration")),
+        // The mentions without a username are taken from the entities before
+        ArgKind::User => match token.value.strip_prefix('@') {
+            Some(username) if !username.is_empty() => {
+                Ok(Value::User(Mention::Username(username.to_string())))
+            }
+            _ => Err(invalid("user")),
+        },
+        // Flags aren't positional, a spec can't get here
+        ArgKind::Flag => Err(ArgsError::Unexpected(token.value.clone())),
+    }
+}
+
+// Parses the arguments of the message according to the spec
+pub fn parse(spec: &[Arg], args: &str, message: &Message) -> Result<Args, ArgsError> {
+    let mut parsed = Args::default();
+    let mut tokens = tokenize(args)?;
+
+    let is_flag = |name: &str| {
+        spec.iter()
+            .any(|arg| arg.kind == ArgKind::Flag && arg.name == name)
+    };
+
+    // A lone flag can be written without the dashes, e.g. /gn rafk
+    if let [token] = tokens.as_slice() {
+        if !token.quoted && is_flag(token.value.as_str()) {
+            let arg = spec.iter().find(|arg| arg.name == token.value).unwrap();
+            parsed.flags.insert(arg.name);
+            tokens.clear();
+        }
+    }
+
+    // The index of the positional token the text starts with
+    let text_start = spec
+        .iter()
+        .filter(|arg| arg.kind != ArgKind::Flag)
+        .position(|arg| arg.kind == ArgKind::Text);
+
+    let mut positional = Vec::new();
+    for token in tokens.iter() {
+        match token.value.strip_prefix(FLAG_PREFIX) {
+            Some(name) if !token.quoted && is_flag(name) => {
+                let arg = spec.iter().find(|arg| arg.name == name).unwrap();
+                if text_start.map_or(false, |start| positional.len() > start) {
+                    return Err(ArgsError::MisplacedFlag(arg.name));
+                }
+                parsed.flags.insert(arg.name);
+            }
+            _ => positional.push(token),
+        }
+    }
+
+    let text_mentions = text_mentions(message, args);
+    let mut positional = positional.into_iter().peekable();
+
+    for arg in spec.iter().filter(|arg| arg.kind != ArgKind::Flag) {
+        match positional.next() {
+            Some(token) => {
+                let mention = text_mentions.iter().find(|mention| {
+                    arg.kind == ArgKind::User
+                        && token.start <= mention.start
+                        && mention.start < token.end
+                });
+                let value = match mention {
+                    Some(mention) => {
+                        // The rest of the name is split into the next tokens
+                        while positional
+                            .next_if(|next| next.start < mention.end)
+                            .is_some()
+                        {}
+                        Value::User(Mention::User(mention.user.clone()))
+                    }
+                    None => parse_value(arg, token)?,
+                };
+                parsed.values.insert(arg.name, value);
+
+                if arg.kind == ArgKind::Text {
+                    return Ok(parsed);
+                }
+            }
+            None if arg.kind == ArgKind::User => {
+                let reply_author = message
+                    .reply_to_message
+                    .as_ref()
+                    .and_then(|reply| reply.from.as_ref());
+
+                match reply_author {
+                    Some(user) => {
+                        parsed
+                            .values
+                            .insert(arg.name, Value::User(Mention::User(user.clone())));
+                    }
+                    None if arg.optional => {}
+                    None => return Err(ArgsError::Missing(arg.name)),
+                }
+            }
+            None if arg.optional => {}
+            None => return Err(ArgsError::Missing(arg.name)),
+        }
+    }
+
+    match positional.next() {
+        Some(token) => Err(ArgsError::Unexpected(token.value.clone())),
+        None => Ok(parsed),
+    }
+}
+
+// e.g. /gn [--rafk] [message...]
+pub fn usage(command_name: &str, spec: &[Arg]) -> String {
+    let mut usage = format!("/{}", command_name);
+    for arg in spec.iter() {
+        usage.push(' ');
+        usage += arg.usage().as_str();
+    }
+
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const GN: &[Arg] = &[Arg::flag("rafk"), Arg::optional("message", ArgKind::Text)];
+    const GRANT: &[Arg] = &[
+        Arg::required("role", ArgKind::Word),
+        Arg::required("user", ArgKind::User),
+    ];
+    const MUTE: &[Arg] = &[Arg::required("duration", ArgKind::Duration)];
+
+    fn message(text: &str) -> Message {
+        serde_json::from_value(json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": -1, "type": "supergroup" },
+            "text": text,
+        }))
+        .unwrap()
+    }
+
+    fn user(id: u64, first_name: &str) -> serde_json::Value {
+        json!({ "id": id, "is_bot": false, "first_name": first_name })
+    }
+
+    fn parse_ok(spec: &[Arg], args: &str) -> Args {
+        parse(spec, args, &message(args)).unwrap_or_else(|err| panic!("{:?}", err))
+    }
+
+    fn parse_err(spec: &[Arg], args: &str) -> ArgsError {
+        match parse(spec, args, &message(args)) {
+            Ok(_) => panic!("\"{}\" was parsed", args),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn parses_straight_quotes() {
+        let args = parse_ok(GRANT, r#""chat moderator" @someone"#);
+
+        assert_eq!(args.text("role"), Some("chat moderator"));
+    }
+
+    #[test]
+    fn parses_curly_quotes() {
+        let args = parse_ok(GRANT, "“chat moderator” @someone");
+
+        assert_eq!(args.text("role"), Some("chat moderator"));
+    }
+
+    #[test]
+    fn rejects_unclosed_quote() {
+        assert!(matches!(
+            parse_err(GRANT, r#""chat moderator @someone"#),
+            ArgsError::UnclosedQuote
+        ));
+    }
+
+    #[test]
+    fn parses_flag_with_dashes() {
+        let args = parse_ok(GN, "--rafk");
+
+        assert!(args.flag("rafk"));
+        assert_eq!(args.text("message"), None);
+    }
+
+    #[test]
+    fn parses_lone_flag_without_dashes() {
+        let args = parse_ok(GN, "rafk");
+
+        assert!(args.flag("rafk"));
+        assert_eq!(args.text("message"), None);
+    }
+
+    #[test]
+    fn quoted_flag_is_text() {
+        let args = parse_ok(GN, r#""rafk""#);
+
+        assert!(!args.flag("rafk"));
+        assert_eq!(args.text("message"), Some(r#""rafk""#));
+    }
+
+    #[test]
+    fn flag_word_among_text_is_text() {
+        let args = parse_ok(GN, "rafk and sleep");
+
+        assert!(!args.flag("rafk"));
+        assert_eq!(args.text("message"), Some("rafk and sleep"));
+    }
+
+    #[test]
+    fn parses_flag_before_text() {
+        let args = parse_ok(GN, "--rafk going to sleep ");
+
+        assert!(args.flag("rafk"));
+        assert_eq!(args.text("message"), Some("going to sleep"));
+    }
+
+    #[test]
+    fn rejects_flag_after_text() {
+        assert!(matches!(
+            parse_err(GN, "going to sleep --rafk"),
+            ArgsError::MisplacedFlag("rafk")
+        ));
+    }
+
+    #[test]
+    fn parses_username() {
+        let args = parse_ok(GRANT, "moderator @someone");
+
+        match args.user("user") {
+            Some(Mention::Username(username)) => assert_eq!(username, "someone"),
+            _ => panic!("No username"),
+        }
+    }
+
+    #[test]
+    fn parses_text_mention() {
+        let message: Message = serde_json::from_value(json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": -1, "type": "supergroup" },
+            "text": "/grant moderator Иван",
+            "entities": [
+                { "type": "bot_command", "offset": 0, "length": 6 },
+                { "type": "text_mention", "offset": 17, "length": 4, "user": user(42, "Иван") },
+            ],
+        }))
+        .unwrap();
+
+        let args = parse(GRANT, "moderator Иван", &message).unwrap();
+
+        match args.user("user") {
+            Some(Mention::User(user)) => assert_eq!(user.id, 42),
+            _ => panic!("No mentioned user"),
+        }
+    }
+
+    fn message_with_mention(text: &str, offset: usize, length: usize) -> Message {
+        serde_json::from_value(json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": -1, "type": "supergroup" },
+            "text": text,
+            "entities": [{
+                "type": "text_mention",
+                "offset": offset,
+                "length": length,
+                "user": user(42, "John"),
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn mentioned_user_id(args: &Args) -> Option<u64> {
+        match args.user("user") {
+            Some(Mention::User(user)) => Some(user.id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_text_mention_with_spaces() {
+        let message = message_with_mention("/grant moderator John Smith", 17, 10);
+
+        let args = parse(GRANT, "moderator John Smith", &message).unwrap();
+
+        assert_eq!(mentioned_user_id(&args), Some(42));
+    }
+
+    #[test]
+    fn parses_text_mention_after_emoji() {
+        // 🦀 is 2 UTF-16 code units and 4 bytes
+        let message = message_with_mention("/grant 🦀 John Smith", 10, 10);
+        let spec = &[
+            Arg::required("role", ArgKind::Word),
+            Arg::required("user", ArgKind::User),
+        ];
+
+        let args = parse(spec, "🦀 John Smith", &message).unwrap();
+
+        assert_eq!(args.text("role"), Some("🦀"));
+        assert_eq!(mentioned_user_id(&args), Some(42));
+    }
+
+    #[test]
+    fn parses_text_after_text_mention() {
+        const WARN: &[Arg] = &[
+            Arg::required("user", ArgKind::User),
+            Arg::optional("reason", ArgKind::Text),
+        ];
+        let message = message_with_mention("/warn John Smith spam", 6, 10);
+
+        let args = parse(WARN, "John Smith spam", &message).unwrap();
+
+        assert_eq!(mentioned_user_id(&args), Some(42));
+        assert_eq!(args.text("reason"), Some("spam"));
+    }
+
+    #[test]
+    fn text_mention_only_matches_its_position() {
+        // The same name as plain text isn't the mention
+        let message = message_with_mention("/grant John John", 12, 4);
+
+        let args = parse(GRANT, "John John", &message).unwrap();
+        assert_eq!(args.text("role"), Some("John"));
+        assert_eq!(mentioned_user_id(&args), Some(42));
+    }
+
+    #[test]
+    fn falls_back_to_reply_author() {
+        let message: Message = serde_json::from_value(json!({
+            "message_id": 2,
+            "date": 0,
+            "chat": { "id": -1, "type": "supergroup" },
+            "text": "/grant moderator",
+            "reply_to_message": {
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": -1, "type": "supergroup" },
+                "from": user(42, "Someone"),
+                "text": "hi",
+            },
+        }))
+        .unwrap();
+
+        let args = parse(GRANT, "moderator", &message).unwrap();
+
+        match args.user("user") {
+            Some(Mention::User(user)) => assert_eq!(user.id, 42),
+            _ => panic!("No reply author"),
+        }
+    }
+
+    #[test]
+    fn requires_user_without_reply() {
+        assert!(matches!(
+            parse_err(GRANT, "moderator"),
+            ArgsError::Missing("user")
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_user() {
+        assert!(matches!(
+            parse_err(GRANT, "moderator someone"),
+            ArgsError::Invalid { name: "user", .. }
+        ));
+    }
+
+    #[test]
+    fn parses_duration() {
+        let args = parse_ok(MUTE, "1h30m");
+
+        assert_eq!(args.duration("duration"), Some(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn rejects_invalid_duration() {
+        match parse_err(MUTE, "soon") {
+            ArgsError::Invalid {
+                name,
+                value,
+                expected,
+            } => {
+                assert_eq!(name, "duration");
+                assert_eq!(value, "soon");
+                assert_eq!(expected, "duration");
+            }
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_args() {
+        match parse_err(MUTE, "1h 30m") {
+            ArgsError::Unexpected(value) => assert_eq!(value, "30m"),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn text_takes_trailing_args() {
+        let args = parse_ok(GN, "going   to \"sleep\"");
+
+        assert_eq!(args.text("message"), Some("going   to \"sleep\""));
+    }
+
+    #[test]
+    fn builds_usage() {
+        assert_eq!(usage("gn", GN), "/gn [--rafk] [message...]");
+        assert_eq!(usage("grant", GRANT), "/grant <role> <user>");
+    }
+}
@@ -0,0 +1,116 @@
+use config::ConfigError;
+use serde::Deserialize;
+
+use crate::commands::{Command, CommandParams, CommandResult};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::settings::Settings;
+
+// The texts are taken from the bundle of the user's language unless they're set
+#[derive(Debug, Deserialize)]
+pub struct CommandSettings {
+    pub cancelled_text: Option<String>,
+    pub nothing_to_cancel_text: Option<String>,
+}
+
+pub struct Cancel {
+    settings: CommandSettings,
+}
+
+impl Command for Cancel {
+    fn init(settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self {
+            settings: settings.command_settings("cancel")?,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "cancel"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stop answering the question of a command"
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            cache,
+            message,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let user_id = helpers::get_sender(message)?.id;
+
+        let text = match cache.end_conversation(message.chat.id, user_id) {
+            Some(_) => self
+                .settings
+                .cancelled_text
+                .clone()
+                .unwrap_or_else(|| locale.text("cancel.cancelled_text")),
+            None => self
+                .settings
+                .nothing_to_cancel_text
+                .clone()
+                .unwrap_or_else(|| locale.text("cancel.nothing_to_cancel_text")),
+        };
+
+        helpers::send_text_message(api, message.chat.id, text, Some(message.message_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{bot_user, group, message, random_id, update, user, TestBot};
+
+    const ASK: &str = "Send me your location or /cancel";
+
+    #[test]
+    #[ignore]
+    fn cancel_ends_conversation() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &alice, "/set_my_location"),
+        ));
+        bot.send(update("message", message(2, &chat, &alice, "/cancel")));
+        let mut reply = message(3, &chat, &alice, "London");
+        reply["reply_to_message"] = message(100, &chat, &bot_user(), ASK);
+        bot.send(update("message", reply));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, ASK.to_string(), Some(1)),
+                (chat_id, "Cancelled".to_string(), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn nothing_to_cancel() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &user(random_id(), "alice"), "/cancel"),
+        ));
+
+        assert_eq!(
+            bot.replies(),
+            vec![(
+                chat["id"].as_i64().unwrap(),
+                "There's nothing to cancel".to_string(),
+                Some(1)
+            )]
+        );
+    }
+}
@@ -1,30 +1,40 @@
+use config::ConfigError;
 use serde::Deserialize;
 
 use crate::commands::{Command, CommandParams, CommandResult};
 use crate::errors::HandleUpdateError;
 use crate::helpers;
-
-pub const DONATE: Command = Command {
-    name: "donate",
-    description: "Support the creator",
-    is_admin_only: false,
-    handler,
-    chat_action: None,
-};
+use crate::settings::Settings;
 
 #[derive(Debug, Deserialize)]
 pub struct CommandSettings {
     pub text: String,
 }
 
-fn handler(
-    CommandParams {
-        api,
-        settings,
-        message,
-        ..
-    }: CommandParams,
-) -> CommandResult<HandleUpdateError> {
-    let text = settings.commands.donate.text.clone();
-    helpers::send_text_message(api, message.chat.id, text, Some(message.message_id))
+pub struct Donate {
+    settings: CommandSettings,
+}
+
+impl Command for Donate {
+    fn init(settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self {
+            settings: settings.command_settings("donate")?,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "donate"
+    }
+
+    fn description(&self) -> &'static str {
+        "Support the creator"
+    }
+
+    fn handle(
+        &self,
+        CommandParams { api, message, .. }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let text = self.settings.text.clone();
+        helpers::send_text_message(api, message.chat.id, text, Some(message.message_id))
+    }
 }
@@ -0,0 +1,115 @@
+use config::ConfigError;
+
+use crate::commands::args::{Arg, ArgKind};
+use crate::commands::role::{Role, MODERATOR};
+use crate::commands::{Command, CommandParams, CommandResult};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::services::disabled_command::functions::set_enabled;
+use crate::settings::Settings;
+
+const ARGS: &[Arg] = &[Arg::required("command", ArgKind::Word)];
+
+pub struct Enable;
+
+impl Command for Enable {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "enable"
+    }
+
+    fn description(&self) -> &'static str {
+        "Enable a command in this chat"
+    }
+
+    fn role(&self) -> Role {
+        MODERATOR
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/enable shuffle"]
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        toggle(params, true)
+    }
+}
+
+pub struct Disable;
+
+impl Command for Disable {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "disable"
+    }
+
+    fn description(&self) -> &'static str {
+        "Disable a command in this chat"
+    }
+
+    fn role(&self) -> Role {
+        MODERATOR
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/disable shuffle"]
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        toggle(params, false)
+    }
+}
+
+fn toggle(
+    CommandParams {
+        api,
+        conn,
+        cache,
+        message,
+        args,
+        locale,
+        executor,
+        ..
+    }: CommandParams,
+    enabled: bool,
+) -> CommandResult<HandleUpdateError> {
+    let reply = |key: &str, command: &str| {
+        helpers::send_text_message(
+            api,
+            message.chat.id,
+            locale.render(key, &liquid::object!({ "command": command })),
+            Some(message.message_id),
+        )
+    };
+
+    let name = args.text("command").unwrap().trim_start_matches('/');
+
+    let command = match executor.get(name) {
+        Some(command) if command.role() == Role::Everyone => command,
+        Some(_) => return reply("enable.cant_disable", name),
+        None => return reply("enable.no_command", name),
+    };
+
+    set_enabled(conn, message.chat.id, command.name(), enabled)?;
+    cache.forget_disabled_commands(message.chat.id);
+    executor.send_chat_commands(conn, message.chat.id)?;
+
+    match enabled {
+        true => reply("enable.enabled", command.name()),
+        false => reply("enable.disabled", command.name()),
+    }
+}
@@ -1,56 +1,106 @@
+use config::ConfigError;
+use diesel::PgConnection;
 use frankenstein::ChatAction;
 use serde::Deserialize;
 
+use crate::cache::Cache;
+use crate::commands::args::{Arg, ArgKind};
 use crate::commands::{Command, CommandParams, CommandResult};
 use crate::errors::HandleUpdateError;
+use crate::settings::Settings;
 
 use crate::helpers;
 use crate::services::afk_event::functions::{begin_event, ActionType, EventType};
+use crate::services::chat_text::functions::get_chat_template;
+use crate::services::chat_text::ChatText;
 
-pub const GOOD_NIGHT: Command = Command {
-    name: "gn",
-    description: "Good night, sweet prince!",
-    is_admin_only: false,
-    handler,
-    chat_action: Some(ChatAction::Typing),
-};
+const ARGS: &[Arg] = &[Arg::flag("rafk"), Arg::optional("message", ArgKind::Text)];
 
+// The texts are taken from the bundle of the user's language unless they're set
 #[derive(Debug, Deserialize)]
 pub struct CommandSettings {
     pub good_night_text: Option<String>,
 }
 
-fn handler(
-    CommandParams {
-        api,
-        conn,
-        settings,
-        cache,
-        message,
-        args,
-        ..
-    }: CommandParams,
-) -> CommandResult<HandleUpdateError> {
-    let user = message.from.as_ref().unwrap();
-    let action_type = match args {
-        "rafk" => ActionType::Continue,
-        _ => ActionType::New,
+// The text the chat admins have set with /settext, or the default one
+pub fn chat_text(
+    conn: &mut PgConnection,
+    cache: &Cache,
+    chat_id: i64,
+    default: &str,
+) -> Result<String, HandleUpdateError> {
+    let text = match get_chat_template(conn, cache, chat_id, ChatText::GoodNightText)? {
+        Some(template) => template
+            .render(&liquid::Object::new())
+            .expect("Failed to render a template"),
+        None => default.to_string(),
     };
-    let afk_message = match args.is_empty() {
-        true => None,
-        false => Some(args.to_string()),
-    };
-    let event = begin_event(conn, user, EventType::Sleep, action_type, afk_message)?;
-    cache.cache_afk_event_id(user.id as i64, true, event.id);
-    helpers::send_text_message(
-        api,
-        message.chat.id,
-        settings
-            .commands
-            .gn
-            .good_night_text
+
+    Ok(text)
+}
+
+pub struct GoodNight {
+    text: Option<String>,
+}
+
+impl Command for GoodNight {
+    fn init(settings: &Settings) -> Result<Self, ConfigError> {
+        let settings: CommandSettings = settings.command_settings("gn")?;
+
+        Ok(Self {
+            text: settings.good_night_text,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "gn"
+    }
+
+    fn description(&self) -> &'static str {
+        "Good night, sweet prince!"
+    }
+
+    fn chat_action(&self) -> Option<ChatAction> {
+        Some(ChatAction::Typing)
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/gn", "/gn see you tomorrow", "/gn rafk"]
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            conn,
+            cache,
+            message,
+            args,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let user = helpers::get_sender(message)?;
+        let action_type = match args.flag("rafk") {
+            true => ActionType::Continue,
+            false => ActionType::New,
+        };
+        let afk_message = args.text("message").map(String::from);
+        let event = begin_event(conn, user, EventType::Sleep, action_type, afk_message)?;
+        cache.cache_afk_event_id(user.id as i64, true, event.id);
+        let default = self
+            .text
             .clone()
-            .unwrap_or_else(|| "Good night!".into()),
-        Some(message.message_id),
-    )
+            .unwrap_or_else(|| locale.text("gn.good_night_text"));
+        helpers::send_text_message(
+            api,
+            message.chat.id,
+            chat_text(conn, cache, message.chat.id, default.as_str())?,
+            Some(message.message_id),
+        )
+    }
 }
@@ -0,0 +1,141 @@
+use config::ConfigError;
+
+use crate::commands::args::{Arg, ArgKind, Mention};
+use crate::commands::role::Role;
+use crate::commands::{Command, CommandParams, CommandResult};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::services::user::errors::ServiceError;
+use crate::services::user::functions::get_by_username;
+use crate::services::user_role::functions::{grant, revoke};
+use crate::settings::Settings;
+
+const ARGS: &[Arg] = &[
+    Arg::required("role", ArgKind::Word),
+    Arg::required("user", ArgKind::User),
+];
+
+pub struct Grant;
+
+impl Command for Grant {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "grant"
+    }
+
+    fn description(&self) -> &'static str {
+        "Grant a role to a user in this chat"
+    }
+
+    fn role(&self) -> Role {
+        Role::ChatAdmin
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/grant moderator @username"]
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        change_role(params, true)
+    }
+}
+
+pub struct Revoke;
+
+impl Command for Revoke {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "revoke"
+    }
+
+    fn description(&self) -> &'static str {
+        "Revoke a role from a user in this chat"
+    }
+
+    fn role(&self) -> Role {
+        Role::ChatAdmin
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/revoke moderator @username"]
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        change_role(params, false)
+    }
+}
+
+fn change_role(
+    CommandParams {
+        api,
+        conn,
+        message,
+        args,
+        locale,
+        executor,
+        ..
+    }: CommandParams,
+    granted: bool,
+) -> CommandResult<HandleUpdateError> {
+    let reply = |key: &str, globals: liquid::Object| {
+        helpers::send_text_message(
+            api,
+            message.chat.id,
+            locale.render(key, &globals),
+            Some(message.message_id),
+        )
+    };
+
+    let roles = executor.custom_roles();
+    let name = args.text("role").unwrap();
+    let role = match roles.iter().find(|role| **role == name) {
+        Some(role) => *role,
+        None => {
+            return reply(
+                "grant.unknown_role",
+                liquid::object!({ "roles": roles.join(", ") }),
+            )
+        }
+    };
+
+    let (user_id, user_name) = match args.user("user").unwrap() {
+        Mention::User(user) => (user.id, user.first_name.clone()),
+        Mention::Username(username) => match get_by_username(conn, username) {
+            Ok(user) => (user.telegram_uid as u64, format!("@{}", username)),
+            Err(ServiceError::NotFound) => {
+                return reply(
+                    "grant.unknown_user",
+                    liquid::object!({ "username": username }),
+                );
+            }
+            Err(err) => return Err(err.into()),
+        },
+    };
+
+    let key = match granted {
+        true => {
+            grant(conn, message.chat.id, user_id, role)?;
+            "grant.granted"
+        }
+        false => match revoke(conn, message.chat.id, user_id, role)? {
+            true => "grant.revoked",
+            false => "grant.not_granted",
+        },
+    };
+
+    reply(key, liquid::object!({ "user": user_name, "role": role }))
+}
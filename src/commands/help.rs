@@ -0,0 +1,114 @@
+use config::ConfigError;
+
+use crate::commands::args::{Arg, ArgKind};
+use crate::commands::role::Role;
+use crate::commands::{Command, CommandParams, CommandResult, CommandsExecutor};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::i18n::Locale;
+use crate::settings::Settings;
+
+const ARGS: &[Arg] = &[Arg::optional("command", ArgKind::Word)];
+
+pub struct Help;
+
+impl Command for Help {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the commands or show how to use one"
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/help", "/help weather"]
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        handler(self, params)
+    }
+}
+
+fn command_help(command: &dyn Command, executor: &CommandsExecutor, locale: &Locale) -> String {
+    let mut lines = vec![
+        command.usage(),
+        executor.description(command, locale.language()),
+    ];
+    if command.role() != Role::Everyone {
+        lines.push(locale.render(
+            "help.needs_role",
+            &liquid::object!({ "role": command.role().name() }),
+        ));
+    }
+    if !command.examples().is_empty() {
+        lines.push(format!("\n{}", locale.text("help.examples")));
+        lines.extend(command.examples().iter().map(|example| example.to_string()));
+    }
+
+    lines.join("\n")
+}
+
+fn handler(
+    help: &Help,
+    CommandParams {
+        api,
+        conn,
+        message,
+        args,
+        locale,
+        executor,
+        ..
+    }: CommandParams,
+) -> CommandResult<HandleUpdateError> {
+    let disabled = executor.disabled_commands(conn, message.chat.id)?;
+
+    // Only the commands the user can call in this chat are shown
+    let mut visible = Vec::new();
+    for command in executor.commands() {
+        if !disabled.iter().any(|name| name == command.name())
+            && executor.can_call(conn, command, message)?
+        {
+            visible.push(command);
+        }
+    }
+
+    let text = match args.text("command") {
+        Some(name) => {
+            let name = name.trim_start_matches('/');
+            match visible.iter().find(|command| command.name() == name) {
+                Some(command) => command_help(*command, executor, locale),
+                None => locale.render("help.no_command", &liquid::object!({ "command": name })),
+            }
+        }
+        None => {
+            let mut lines: Vec<String> = visible
+                .iter()
+                .map(|command| {
+                    format!(
+                        "/{} - {}{}",
+                        command.name(),
+                        command.role().label(locale).unwrap_or_default(),
+                        executor.description(*command, locale.language())
+                    )
+                })
+                .collect();
+            lines.push(format!(
+                "\n{}",
+                locale.render("help.details", &liquid::object!({ "usage": help.usage() }))
+            ));
+
+            lines.join("\n")
+        }
+    };
+
+    helpers::send_text_message(api, message.chat.id, text, Some(message.message_id))
+}
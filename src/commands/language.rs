@@ -0,0 +1,97 @@
+use config::ConfigError;
+
+use crate::commands::args::{Arg, ArgKind};
+use crate::commands::{Command, CommandParams, CommandResult};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::i18n;
+use crate::services::user::functions::set_language;
+use crate::settings::Settings;
+
+// Goes back to the language of the user's Telegram app
+const AUTO: &str = "auto";
+
+const ARGS: &[Arg] = &[Arg::optional("language", ArgKind::Word)];
+
+pub struct Language;
+
+impl Command for Language {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "language"
+    }
+
+    fn description(&self) -> &'static str {
+        "Choose the language"
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/language", "/language ru", "/language auto"]
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            conn,
+            cache,
+            settings,
+            message,
+            args,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let reply = |text: String| {
+            helpers::send_text_message(api, message.chat.id, text, Some(message.message_id))
+        };
+
+        let from = helpers::get_sender(message)?;
+        let language = match args.text("language") {
+            Some(language) => language.to_lowercase(),
+            None => {
+                return reply(locale.render(
+                    "language.current",
+                    &liquid::object!({
+                        "language": locale.language(),
+                        "languages": i18n::language_list(),
+                    }),
+                ))
+            }
+        };
+
+        // The users are answered in the language they've switched to
+        if language == AUTO {
+            set_language(conn, from, None)?;
+            cache.cache_language(from.id, None);
+
+            let locale = i18n::resolve(None, Some(from), settings.default_language());
+            return reply(locale.text("language.reset"));
+        }
+
+        if !i18n::is_supported(language.as_str()) {
+            return reply(locale.render(
+                "language.unknown",
+                &liquid::object!({
+                    "language": language,
+                    "languages": i18n::language_list(),
+                }),
+            ));
+        }
+
+        set_language(conn, from, Some(language.as_str()))?;
+        cache.cache_language(from.id, Some(language.clone()));
+
+        reply(i18n::locale(language.as_str()).render(
+            "language.changed",
+            &liquid::object!({ "language": language }),
+        ))
+    }
+}
@@ -0,0 +1,64 @@
+use config::ConfigError;
+
+use crate::commands::role::{Role, MODERATOR};
+use crate::commands::{Command, CommandParams, CommandResult};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::settings::Settings;
+
+pub struct ListCommands;
+
+impl Command for ListCommands {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "commands"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the commands enabled in this chat"
+    }
+
+    fn role(&self) -> Role {
+        MODERATOR
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        handler(params)
+    }
+}
+
+fn handler(
+    CommandParams {
+        api,
+        conn,
+        message,
+        locale,
+        executor,
+        ..
+    }: CommandParams,
+) -> CommandResult<HandleUpdateError> {
+    let disabled = executor.disabled_commands(conn, message.chat.id)?;
+
+    let lines: Vec<String> = executor
+        .commands()
+        .into_iter()
+        .filter(|command| command.role() == Role::Everyone)
+        .map(|command| {
+            let key = match disabled.iter().any(|name| name == command.name()) {
+                true => "commands.disabled",
+                false => "commands.enabled",
+            };
+            locale.render(key, &liquid::object!({ "command": command.name() }))
+        })
+        .collect();
+
+    helpers::send_text_message(
+        api,
+        message.chat.id,
+        lines.join("\n"),
+        Some(message.message_id),
+    )
+}
@@ -1,79 +1,100 @@
+use config::ConfigError;
 use frankenstein::ChatAction;
 use serde::Deserialize;
 
-use crate::commands::{Command, CommandParams, CommandResult};
+use crate::commands::{gn, work, Command, CommandParams, CommandResult};
 use crate::errors::HandleUpdateError;
+use crate::settings::Settings;
 
-use crate::helpers::send_text_message;
+use crate::helpers::{get_sender, send_text_message};
 use crate::services::afk_event::functions::EventType;
 use crate::services::afk_event::{
     errors::ServiceError as AfkEventServiceError, functions::reset_latest_event,
 };
 use crate::services::user::errors::ServiceError as UserServiceError;
 
-pub const RAFK: Command = Command {
-    name: "rafk",
-    description: "Resume your AFK",
-    is_admin_only: false,
-    handler,
-    chat_action: Some(ChatAction::Typing),
-};
-
+// The texts are taken from the bundle of the user's language unless they're set
 #[derive(Debug, Deserialize)]
 pub struct CommandSettings {
     pub no_afk_event_text: Option<String>,
 }
 
-fn handler(
-    CommandParams {
-        api,
-        cache,
-        conn,
-        settings,
-        message,
-        ..
-    }: CommandParams,
-) -> CommandResult<HandleUpdateError> {
-    let user = message.from.as_ref().unwrap();
+pub struct Rafk {
+    no_afk_event_text: Option<String>,
+    // The texts of /gn and /work, the resumed AFK is greeted the same way
+    good_night_text: Option<String>,
+    work_text: Option<String>,
+}
+
+impl Command for Rafk {
+    fn init(settings: &Settings) -> Result<Self, ConfigError> {
+        let own: CommandSettings = settings.command_settings("rafk")?;
+        let gn: gn::CommandSettings = settings.command_settings("gn")?;
+        let work: work::CommandSettings = settings.command_settings("work")?;
+
+        Ok(Self {
+            no_afk_event_text: own.no_afk_event_text,
+            good_night_text: gn.good_night_text,
+            work_text: work.work_text,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "rafk"
+    }
+
+    fn description(&self) -> &'static str {
+        "Resume your AFK"
+    }
+
+    fn chat_action(&self) -> Option<ChatAction> {
+        Some(ChatAction::Typing)
+    }
 
-    let send_no_afk_event_message = || {
-        send_text_message(
+    fn handle(
+        &self,
+        CommandParams {
             api,
-            message.chat.id,
-            settings
-                .commands
-                .rafk
-                .no_afk_event_text
-                .clone()
-                .unwrap_or_else(|| "You haven't been afk tho...".into()),
-            Some(message.message_id),
-        )
-    };
+            cache,
+            conn,
+            message,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let user = get_sender(message)?;
 
-    match reset_latest_event(conn, user) {
-        Ok(event) => {
-            let text = match event.event_type() {
-                EventType::Work => settings
-                    .commands
-                    .work
-                    .work_text
-                    .clone()
-                    .unwrap_or_else(|| "Have a good one, king.".into()),
-                EventType::Sleep => settings
-                    .commands
-                    .gn
-                    .good_night_text
-                    .clone()
-                    .unwrap_or_else(|| "Good night!".into()),
-            };
+        match reset_latest_event(conn, user) {
+            Ok(event) => {
+                let text = match event.event_type() {
+                    EventType::Work => self
+                        .work_text
+                        .clone()
+                        .unwrap_or_else(|| locale.text("work.work_text")),
+                    EventType::Sleep => {
+                        let default = self
+                            .good_night_text
+                            .clone()
+                            .unwrap_or_else(|| locale.text("gn.good_night_text"));
+                        gn::chat_text(conn, cache, message.chat.id, default.as_str())?
+                    }
+                };
 
-            cache.cache_afk_event_id(user.id as i64, true, event.id);
-            send_text_message(api, message.chat.id, text, Some(message.message_id))
+                cache.cache_afk_event_id(user.id as i64, true, event.id);
+                send_text_message(api, message.chat.id, text, Some(message.message_id))
+            }
+            Err(err) => match err {
+                AfkEventServiceError::NotFound
+                | AfkEventServiceError::User(UserServiceError::NotFound) => send_text_message(
+                    api,
+                    message.chat.id,
+                    self.no_afk_event_text
+                        .clone()
+                        .unwrap_or_else(|| locale.text("rafk.no_afk_event_text")),
+                    Some(message.message_id),
+                ),
+                err => Err(err.into()),
+            },
         }
-        Err(err) => match err {
-            AfkEventServiceError::NotFound
-            | AfkEventServiceError::User(UserServiceError::NotFound) => send_no_afk_event_message(),
-            err => Err(err.into()),
-        },
     }
 }
@@ -0,0 +1,34 @@
+use crate::i18n::Locale;
+
+// Who can call a command, a role includes the ones below it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Everyone,
+    // Granted per chat with /grant, chat admins have all of them
+    Custom(&'static str),
+    // Administrators of the chat the command is called in
+    ChatAdmin,
+    // Settings::admins, allowed to do anything anywhere
+    Owner,
+}
+
+pub const MODERATOR: Role = Role::Custom("moderator");
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Self::Everyone => "everyone",
+            Self::Custom(name) => name,
+            Self::ChatAdmin => "admin",
+            Self::Owner => "owner",
+        }
+    }
+
+    // Shown next to the command description, e.g. [admin only] Disable a command in this chat
+    pub fn label(&self, locale: &Locale) -> Option<String> {
+        match *self {
+            Self::Everyone => None,
+            role => Some(locale.render("roles.label", &liquid::object!({ "role": role.name() }))),
+        }
+    }
+}
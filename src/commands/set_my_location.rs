@@ -1,43 +1,240 @@
-use crate::commands::{Command, CommandParams, CommandResult};
+use config::ConfigError;
+use diesel::PgConnection;
+use frankenstein::{Location, Message};
+
+use crate::client::Client;
+use crate::commands::{AnswerParams, Command, CommandParams, CommandResult};
 use crate::errors::HandleUpdateError;
 use crate::helpers;
+use crate::i18n::Locale;
 use crate::services::user::functions;
+use crate::settings::Settings;
+
+const WAITING_FOR_LOCATION: &str = "location";
+
+pub struct SetMyLocation;
+
+impl SetMyLocation {
+    fn set_location(
+        api: &dyn Client,
+        conn: &mut PgConnection,
+        message: &Message,
+        location: &Location,
+        locale: &Locale,
+    ) -> CommandResult<HandleUpdateError> {
+        functions::set_location(
+            conn,
+            helpers::get_sender(message)?,
+            location.latitude,
+            location.longitude,
+        )
+        .map(|_| ())
+        .map_err(|e| HandleUpdateError::Command(e.to_string()))?;
+
+        helpers::send_text_message(
+            api,
+            message.chat.id,
+            locale.text("set_my_location.location_set"),
+            Some(message.message_id),
+        )
+    }
+}
+
+impl Command for SetMyLocation {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "set_my_location"
+    }
+
+    fn description(&self) -> &'static str {
+        "Set the location as my location"
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            conn,
+            message,
+            locale,
+            executor,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        // Without a reply the location is asked for
+        let reply = match message.reply_to_message.as_ref() {
+            Some(reply) => reply,
+            None => {
+                return executor.ask(
+                    self,
+                    message,
+                    WAITING_FOR_LOCATION,
+                    locale.text("set_my_location.ask"),
+                )
+            }
+        };
+        let location = reply
+            .location
+            .as_ref()
+            .ok_or(HandleUpdateError::MissingReply("location_message"))?;
+
+        Self::set_location(api, conn, message, location, locale)
+    }
+
+    fn handle_answer(
+        &self,
+        AnswerParams {
+            api,
+            conn,
+            message,
+            state,
+            addressed,
+            locale,
+            executor,
+            ..
+        }: AnswerParams,
+    ) -> Result<bool, HandleUpdateError> {
+        match message.location.as_ref() {
+            Some(location) => Self::set_location(api, conn, message, location, locale)?,
+            // The other messages in groups are left alone, they might be meant for someone else
+            None if !addressed => return Ok(false),
+            None => executor.ask(
+                self,
+                message,
+                state,
+                locale.text("set_my_location.ask_again"),
+            )?,
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_json::Value;
+
+    use crate::testing::{
+        bot_user, group, location_message, message, private, random_id, update, user, TestBot,
+    };
+
+    const ASK: &str = "Send me your location or /cancel";
+    const ASK_AGAIN: &str = "That's not a location, send me one or /cancel";
+    const LOCATION_SET: &str = "Location set!";
+
+    // A reply to the question of the bot
+    fn reply(message_id: i32, chat: &Value, from: &Value, text: &str) -> Value {
+        let mut reply = message(message_id, chat, from, text);
+        reply["reply_to_message"] = message(100, chat, &bot_user(), ASK);
+        reply
+    }
+
+    #[test]
+    #[ignore]
+    fn only_replies_to_bot_are_answers_in_groups() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &alice, "/set_my_location"),
+        ));
+        // Not meant for the bot, the question stays open
+        bot.send(update("message", message(2, &chat, &alice, "brb")));
+        bot.send(update("message", reply(3, &chat, &alice, "London")));
+        let mut location = location_message(4, &chat, &alice);
+        location["reply_to_message"] = message(101, &chat, &bot_user(), ASK_AGAIN);
+        bot.send(update("message", location));
+        // The conversation is over
+        bot.send(update("message", reply(5, &chat, &alice, "London")));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, ASK.to_string(), Some(1)),
+                (chat_id, ASK_AGAIN.to_string(), Some(3)),
+                (chat_id, LOCATION_SET.to_string(), Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn other_users_dont_answer() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &alice, "/set_my_location"),
+        ));
+        bot.send(update(
+            "message",
+            reply(2, &chat, &user(random_id(), "bob"), "London"),
+        ));
+        bot.send(update("message", location_message(3, &chat, &alice)));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, ASK.to_string(), Some(1)),
+                (chat_id, LOCATION_SET.to_string(), Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn every_message_is_answer_in_private_chats() {
+        let bot = TestBot::new("");
+        let alice = user(random_id(), "alice");
+        let chat = private(&alice);
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &alice, "/set_my_location"),
+        ));
+        bot.send(update("message", message(2, &chat, &alice, "London")));
+        bot.send(update("message", location_message(3, &chat, &alice)));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, ASK.to_string(), Some(1)),
+                (chat_id, ASK_AGAIN.to_string(), Some(2)),
+                (chat_id, LOCATION_SET.to_string(), Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn expired_question_isnt_answered() {
+        let bot = TestBot::new("[conversations]\ntimeout = 1");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &alice, "/set_my_location"),
+        ));
+        thread::sleep(Duration::from_millis(1100));
+        bot.send(update("message", reply(2, &chat, &alice, "London")));
 
-pub const SET_MY_LOCATION: Command = Command {
-    name: "set_my_location",
-    description: "Set the location as my location",
-    is_admin_only: false,
-    handler,
-    chat_action: None,
-};
-
-fn handler(
-    CommandParams {
-        api, conn, message, ..
-    }: CommandParams,
-) -> CommandResult<HandleUpdateError> {
-    let location = message
-        .reply_to_message
-        .as_ref()
-        .ok_or_else(|| HandleUpdateError::Command("reply to message is empty".into()))?
-        .location
-        .as_ref()
-        .ok_or_else(|| HandleUpdateError::Command("location in the reply is empty".into()))?;
-
-    functions::set_location(
-        conn,
-        message.from.as_ref().unwrap(),
-        location.latitude,
-        location.longitude,
-    )
-    .map(|_| ())
-    .map_err(|e| HandleUpdateError::Command(e.to_string()))?;
-
-    // todo: replace "Location set" with a setting
-    helpers::send_text_message(
-        api,
-        message.chat.id,
-        "Location set!".into(),
-        Some(message.message_id),
-    )
+        assert_eq!(
+            bot.replies(),
+            vec![(chat["id"].as_i64().unwrap(), ASK.to_string(), Some(1))]
+        );
+    }
 }
@@ -1,30 +1,67 @@
-use std::str::ParseBoolError;
+use config::ConfigError;
 
+use crate::commands::args::{Arg, ArgKind, Mention};
+use crate::commands::role::Role;
 use crate::commands::{Command, CommandParams, CommandResult};
 use crate::errors::HandleUpdateError;
+use crate::helpers;
 use crate::services::user::functions;
+use crate::settings::Settings;
 
-pub const SET_PAYING_STATUS: Command = Command {
-    name: "set_paying_status",
-    description: "Set the paying status for a user",
-    is_admin_only: true,
-    handler,
-    chat_action: None,
-};
-
-fn handler(
-    CommandParams {
-        conn,
-        message,
-        args,
-        ..
-    }: CommandParams,
-) -> CommandResult<HandleUpdateError> {
-    let is_paying: bool = args
-        .parse()
-        .map_err(|e: ParseBoolError| HandleUpdateError::Command(e.to_string()))?;
-
-    functions::set_paying_status(conn, message.from.as_ref().unwrap(), is_paying)
+const ARGS: &[Arg] = &[
+    Arg::required("is_paying", ArgKind::Bool),
+    Arg::optional("user", ArgKind::User),
+];
+
+pub struct SetPayingStatus;
+
+impl Command for SetPayingStatus {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "set_paying_status"
+    }
+
+    fn description(&self) -> &'static str {
+        "Set the paying status for a user"
+    }
+
+    fn role(&self) -> Role {
+        Role::Owner
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &[
+            "/set_paying_status true",
+            "/set_paying_status false @username",
+        ]
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            conn,
+            message,
+            args,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let is_paying = args.bool("is_paying").unwrap();
+
+        match args.user("user") {
+            Some(Mention::Username(username)) => {
+                functions::set_paying_status_by_username(conn, username, is_paying)
+            }
+            Some(Mention::User(user)) => functions::set_paying_status(conn, user, is_paying),
+            None => functions::set_paying_status(conn, helpers::get_sender(message)?, is_paying),
+        }
         .map(|_| ())
-        .map_err(|e| HandleUpdateError::Command(e.to_string()))
+        .map_err(HandleUpdateError::from)
+    }
 }
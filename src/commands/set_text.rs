@@ -0,0 +1,214 @@
+use config::ConfigError;
+
+use crate::commands::args::{Arg, ArgKind};
+use crate::commands::role::Role;
+use crate::commands::{Command, CommandParams, CommandResult};
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::services::chat_text::functions::{reset_template, set_template};
+use crate::services::chat_text::ChatText;
+use crate::settings::Settings;
+
+const ARGS: &[Arg] = &[
+    Arg::required("text", ArgKind::Word),
+    Arg::optional("template", ArgKind::Text),
+];
+
+pub struct SetText;
+
+impl Command for SetText {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "settext"
+    }
+
+    fn description(&self) -> &'static str {
+        "Change a text in this chat, without a template it's reset to the default one"
+    }
+
+    fn role(&self) -> Role {
+        Role::ChatAdmin
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &[
+            "/settext wake_up_format {{ username }} is up after {{ duration }}",
+            "/settext good_night_text Sweet dreams!",
+            "/settext good_night_text",
+        ]
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            conn,
+            cache,
+            message,
+            args,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let reply = |key: &str, globals: liquid::Object| {
+            helpers::send_text_message(
+                api,
+                message.chat.id,
+                locale.render(key, &globals),
+                Some(message.message_id),
+            )
+        };
+
+        let key = args.text("text").unwrap();
+        let text = match ChatText::from_key(key) {
+            Some(text) => text,
+            None => {
+                let keys: Vec<&str> = ChatText::ALL.iter().map(ChatText::key).collect();
+                return reply(
+                    "settext.unknown_text",
+                    liquid::object!({ "texts": keys.join(", ") }),
+                );
+            }
+        };
+        let globals = liquid::object!({ "text": text.key() });
+
+        let template = match args.text("template") {
+            Some(template) => template,
+            None => {
+                let reset = reset_template(conn, message.chat.id, text.key())?;
+                cache.forget_chat_template(message.chat.id, text);
+
+                return match reset {
+                    true => reply("settext.reset", globals),
+                    false => reply("settext.already_default", globals),
+                };
+            }
+        };
+
+        if let Err(err) = text.parse(template) {
+            return reply(
+                "settext.invalid_template",
+                liquid::object!({ "error": err.to_string() }),
+            );
+        }
+        set_template(conn, message.chat.id, text.key(), template)?;
+        cache.forget_chat_template(message.chat.id, text);
+
+        reply("settext.changed", globals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::testing::{group, message, random_id, update, user, TestBot};
+
+    // One of the admins of the test config
+    fn owner() -> Value {
+        user(2, "owner")
+    }
+
+    #[test]
+    #[ignore]
+    fn rejects_invalid_template() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update(
+            "message",
+            message(
+                1,
+                &chat,
+                &owner(),
+                "/settext wake_up_format {{ nobody }} is up",
+            ),
+        ));
+        bot.send(update("message", message(2, &chat, &alice, "/gn bye")));
+        bot.send(update("message", message(3, &chat, &alice, "morning")));
+
+        let replies = bot.replies();
+        assert_eq!(replies.len(), 3);
+        assert!(replies[0]
+            .1
+            .starts_with("There's an error in the template!"));
+        // The config's template is still used
+        assert_eq!(replies[2].1, "alice is awake: bye");
+    }
+
+    #[test]
+    #[ignore]
+    fn changed_and_reset_texts_apply_at_once() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let gn = |message_id: i32, username: &str| {
+            bot.send(update(
+                "message",
+                message(message_id, &chat, &user(random_id(), username), "/gn"),
+            ))
+        };
+
+        // Caches the default text of the chat
+        gn(1, "alice");
+        bot.send(update(
+            "message",
+            message(2, &chat, &owner(), "/settext good_night_text Sweet dreams!"),
+        ));
+        gn(3, "bob");
+        bot.send(update(
+            "message",
+            message(4, &chat, &owner(), "/settext good_night_text"),
+        ));
+        gn(5, "carol");
+        bot.send(update(
+            "message",
+            message(6, &chat, &owner(), "/settext good_night_text"),
+        ));
+
+        let texts: Vec<String> = bot.replies().into_iter().map(|reply| reply.1).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Good night!",
+                "good_night_text is changed in this chat",
+                "Sweet dreams!",
+                "good_night_text is reset to the default",
+                "Good night!",
+                "good_night_text is already the default",
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn reset_text_falls_back_to_config() {
+        let bot = TestBot::new("[commands.gn]\ngood_night_text = \"Nighty night\"");
+        let chat = group(random_id());
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &owner(), "/settext good_night_text Sweet dreams!"),
+        ));
+        bot.send(update(
+            "message",
+            message(2, &chat, &owner(), "/settext good_night_text"),
+        ));
+        bot.send(update(
+            "message",
+            message(3, &chat, &user(random_id(), "alice"), "/gn"),
+        ));
+
+        assert_eq!(
+            bot.replies().last().map(|reply| reply.1.as_str()),
+            Some("Nighty night")
+        );
+    }
+}
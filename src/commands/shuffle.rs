@@ -1,70 +1,104 @@
+use config::ConfigError;
 use frankenstein::ChatAction;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use serde::Deserialize;
 
-use crate::commands::{Command, CommandParams, CommandResult};
+use crate::commands::args::{Arg, ArgKind};
+use crate::commands::{cooldown, Command, CommandParams, CommandResult, Cooldown};
 use crate::errors::HandleUpdateError;
 use crate::helpers;
+use crate::settings::Settings;
 
-pub const SHUFFLE: Command = Command {
-    name: "shuffle",
-    description: "Shuffle words",
-    is_admin_only: false,
-    handler,
-    chat_action: Some(ChatAction::Typing),
-};
+const ARGS: &[Arg] = &[Arg::optional("text", ArgKind::Text)];
 
+// The texts are taken from the bundle of the user's language unless they're set
 #[derive(Debug, Deserialize)]
 pub struct CommandSettings {
     pub nothing_to_shuffle_text: Option<String>,
+    // Seconds, 0 turns the cooldown off
+    cooldown_per_user: Option<u64>,
 }
 
-fn handler(
-    CommandParams {
-        api,
-        message,
-        settings,
-        args,
-        ..
-    }: CommandParams,
-) -> CommandResult<HandleUpdateError> {
-    let nothing_to_shuffle = settings
-        .commands
-        .shuffle
-        .nothing_to_shuffle_text
-        .clone()
-        .unwrap_or_else(|| "Nothing to shuffle!".into());
-    let text: Option<String>;
-
-    if !args.is_empty() {
-        text = Some(args.to_string());
-    } else {
-        text = message
-            .reply_to_message
-            .as_ref()
-            .and_then(|reply| reply.text.clone());
+pub struct Shuffle {
+    settings: CommandSettings,
+}
+
+impl Command for Shuffle {
+    fn init(settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self {
+            settings: settings.command_settings("shuffle")?,
+        })
     }
 
-    if text.is_none() {
-        return helpers::send_text_message(
-            api,
-            message.chat.id,
-            nothing_to_shuffle,
-            Some(message.message_id),
-        );
+    fn name(&self) -> &'static str {
+        "shuffle"
+    }
+
+    fn description(&self) -> &'static str {
+        "Shuffle words"
+    }
+
+    fn chat_action(&self) -> Option<ChatAction> {
+        Some(ChatAction::Typing)
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
     }
 
-    let text = text.unwrap();
+    fn examples(&self) -> &'static [&'static str] {
+        &["/shuffle one two three"]
+    }
+
+    fn cooldown(&self) -> Cooldown {
+        Cooldown {
+            per_user: cooldown(self.settings.cooldown_per_user, 5),
+            per_chat: None,
+        }
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            message,
+            args,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let text = match args.text("text") {
+            Some(text) => Some(text.to_string()),
+            None => message
+                .reply_to_message
+                .as_ref()
+                .and_then(|reply| reply.text.clone()),
+        };
+
+        if text.is_none() {
+            return helpers::send_text_message(
+                api,
+                message.chat.id,
+                self.settings
+                    .nothing_to_shuffle_text
+                    .clone()
+                    .unwrap_or_else(|| locale.text("shuffle.nothing_to_shuffle_text")),
+                Some(message.message_id),
+            );
+        }
 
-    let mut rng = thread_rng();
-    let mut words: Vec<&str> = text.split(' ').collect();
-    words.shuffle(&mut rng);
+        let text = text.unwrap();
 
-    helpers::send_text_message(
-        api,
-        message.chat.id,
-        words.join(" "),
-        Some(message.message_id),
-    )
+        let mut rng = thread_rng();
+        let mut words: Vec<&str> = text.split(' ').collect();
+        words.shuffle(&mut rng);
+
+        helpers::send_text_message(
+            api,
+            message.chat.id,
+            words.join(" "),
+            Some(message.message_id),
+        )
+    }
 }
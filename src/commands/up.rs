@@ -1,20 +1,39 @@
+use config::ConfigError;
+
 use crate::commands::{Command, CommandParams, CommandResult};
 use crate::errors::HandleUpdateError;
 use crate::helpers;
+use crate::settings::Settings;
+
+pub struct Up;
+
+impl Command for Up {
+    fn init(_settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self)
+    }
+
+    fn name(&self) -> &'static str {
+        "up"
+    }
 
-pub const UP: Command = Command {
-    name: "up",
-    description: "Check bot status",
-    is_admin_only: false,
-    handler,
-    chat_action: None,
-};
+    fn description(&self) -> &'static str {
+        "Check bot status"
+    }
 
-fn handler(CommandParams { api, message, .. }: CommandParams) -> CommandResult<HandleUpdateError> {
-    helpers::send_text_message(
-        api,
-        message.chat.id,
-        "I'm good.".into(),
-        Some(message.message_id),
-    )
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            message,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        helpers::send_text_message(
+            api,
+            message.chat.id,
+            locale.text("up.text"),
+            Some(message.message_id),
+        )
+    }
 }
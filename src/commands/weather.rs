@@ -1,64 +1,104 @@
+use config::ConfigError;
 use frankenstein::ChatAction;
 use serde::Deserialize;
 use ureq::Error as RequestError;
 
-use crate::commands::{Command, CommandParams, CommandResult};
+use crate::commands::args::{Arg, ArgKind};
+use crate::commands::{cooldown, Command, CommandParams, CommandResult, Cooldown};
 use crate::errors::HandleUpdateError;
 use crate::helpers;
+use crate::services::chat_text::functions::get_chat_template;
+use crate::services::chat_text::ChatText;
 use crate::services::user;
 use crate::services::user::errors::ServiceError;
 use crate::services::user::functions::User;
-use crate::services::weather::{
-    format_weather_data, get_weather, Identifier, WeatherError, WeatherResponse,
-};
-
-pub const WEATHER: Command = Command {
-    name: "weather",
-    description: "Check the weather at a given location",
-    is_admin_only: false,
-    handler,
-    chat_action: Some(ChatAction::FindLocation),
-};
+use crate::services::weather::{format_weather_data, Identifier, WeatherError, WeatherResponse};
+use crate::settings::Settings;
 
+const ARGS: &[Arg] = &[Arg::optional("location", ArgKind::Text)];
+
+// The texts are taken from the bundle of the user's language unless they're set
 #[derive(Debug, Deserialize)]
 pub struct CommandSettings {
     not_found_text: Option<String>,
     no_location_text: Option<String>,
     no_location_for_user_text: Option<String>,
+    // Seconds, 0 turns the cooldown off
+    cooldown_per_user: Option<u64>,
+    cooldown_per_chat: Option<u64>,
+}
+
+pub struct Weather {
+    settings: CommandSettings,
+}
+
+impl Command for Weather {
+    fn init(settings: &Settings) -> Result<Self, ConfigError> {
+        Ok(Self {
+            settings: settings.command_settings("weather")?,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "weather"
+    }
+
+    fn description(&self) -> &'static str {
+        "Check the weather at a given location"
+    }
+
+    fn chat_action(&self) -> Option<ChatAction> {
+        Some(ChatAction::FindLocation)
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/weather London", "/weather"]
+    }
+
+    fn cooldown(&self) -> Cooldown {
+        Cooldown {
+            per_user: cooldown(self.settings.cooldown_per_user, 30),
+            per_chat: cooldown(self.settings.cooldown_per_chat, 5),
+        }
+    }
+
+    fn handle(&self, params: CommandParams) -> CommandResult<HandleUpdateError> {
+        handler(&self.settings, params)
+    }
 }
 
 fn handler(
+    command_settings: &CommandSettings,
     CommandParams {
         api,
+        weather,
         conn,
+        cache,
         settings,
         message,
         args,
+        locale,
         ..
     }: CommandParams,
 ) -> CommandResult<HandleUpdateError> {
     let result: Result<WeatherResponse, WeatherError>;
 
-    let from = message.from.as_ref().unwrap();
+    let from = helpers::get_sender(message)?;
 
     let send_error_message = |user: &frankenstein::User| {
         let text = match user.id == from.id {
-            true => settings
-                .commands
-                .weather
+            true => command_settings
                 .no_location_text
                 .clone()
-                .unwrap_or_else(|| {
-                    "You don't have a location set. Send me a geolocation message \
-                    and call /set_my_location on it."
-                        .into()
-                }),
-            false => settings
-                .commands
-                .weather
+                .unwrap_or_else(|| locale.text("weather.no_location_text")),
+            false => command_settings
                 .no_location_for_user_text
                 .clone()
-                .unwrap_or_else(|| "This user does not have a location set.".into()),
+                .unwrap_or_else(|| locale.text("weather.no_location_for_user_text")),
         };
 
         if let Err(err) =
@@ -66,7 +106,8 @@ fn handler(
         {
             return err;
         }
-        HandleUpdateError::Command("handled return".into())
+        // The user has been told already
+        HandleUpdateError::Skip
     };
 
     let mut get_location_by_user = |user: &frankenstein::User| -> Result<
@@ -84,56 +125,59 @@ fn handler(
         let latitude = latitude.ok_or_else(|| send_error_message(user))?;
         let longitude = longitude.ok_or_else(|| send_error_message(user))?;
 
-        Ok(get_weather(
+        Ok(weather.get_weather(
             Identifier::Location {
                 latitude,
                 longitude,
             },
             settings,
+            locale,
         ))
     };
 
-    if !args.is_empty() {
-        result = get_weather(Identifier::Name(args.to_string()), settings);
+    if let Some(location) = args.text("location") {
+        result = weather.get_weather(Identifier::Name(location.to_string()), settings, locale);
     } else if let Some(reply) = message.reply_to_message.as_ref() {
         if let Some(location) = reply.location.as_ref() {
-            result = get_weather(
+            result = weather.get_weather(
                 Identifier::Location {
                     latitude: location.latitude,
                     longitude: location.longitude,
                 },
                 settings,
+                locale,
             );
         } else {
-            result = get_location_by_user(reply.from.as_ref().unwrap())?;
+            result = get_location_by_user(helpers::get_sender(reply)?)?;
         }
     } else {
         result = get_location_by_user(from)?;
     }
 
     match result {
-        Ok(ref data) => helpers::send_text_message(
+        Ok(ref data) => {
+            let chat_template =
+                get_chat_template(conn, cache, message.chat.id, ChatText::WeatherMessageFormat)?;
+            let template = chat_template
+                .as_deref()
+                .unwrap_or_else(|| settings.open_weather.message_format(locale));
+
+            helpers::send_text_message(
+                api,
+                message.chat.id,
+                format_weather_data(data, template),
+                Some(message.message_id),
+            )
+        }
+        Err(WeatherError::Request(RequestError::Status(404, _))) => helpers::send_text_message(
             api,
             message.chat.id,
-            format_weather_data(&data, &settings),
+            command_settings
+                .not_found_text
+                .clone()
+                .unwrap_or_else(|| locale.text("weather.not_found_text")),
             Some(message.message_id),
         ),
-        Err(err) => match err {
-            WeatherError::Json(io_err) => Err(HandleUpdateError::Command(io_err.to_string())),
-            WeatherError::Request(http_err) => match http_err {
-                RequestError::Status(404, _) => helpers::send_text_message(
-                    api,
-                    message.chat.id,
-                    settings
-                        .commands
-                        .weather
-                        .not_found_text
-                        .clone()
-                        .unwrap_or_else(|| "No weather data for this location found".into()),
-                    Some(message.message_id),
-                ),
-                err => Err(HandleUpdateError::Command(err.to_string())),
-            },
-        },
+        Err(err) => Err(err.into()),
     }
 }
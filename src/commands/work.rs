@@ -1,56 +1,83 @@
+use config::ConfigError;
 use frankenstein::ChatAction;
 use serde::Deserialize;
 
+use crate::commands::args::{Arg, ArgKind};
 use crate::commands::{Command, CommandParams, CommandResult};
 use crate::errors::HandleUpdateError;
+use crate::settings::Settings;
 
 use crate::helpers;
 use crate::services::afk_event::functions::{begin_event, ActionType, EventType};
 
-pub const WORK: Command = Command {
-    name: "work",
-    description: "Yeah but work is like a 3rd-party thing...",
-    is_admin_only: false,
-    handler,
-    chat_action: Some(ChatAction::Typing),
-};
+const ARGS: &[Arg] = &[Arg::flag("rafk"), Arg::optional("message", ArgKind::Text)];
 
+// The texts are taken from the bundle of the user's language unless they're set
 #[derive(Debug, Deserialize)]
 pub struct CommandSettings {
     pub work_text: Option<String>,
 }
 
-fn handler(
-    CommandParams {
-        api,
-        conn,
-        settings,
-        cache,
-        message,
-        args,
-        ..
-    }: CommandParams,
-) -> CommandResult<HandleUpdateError> {
-    let user = message.from.as_ref().unwrap();
-    let action_type = match args {
-        "rafk" => ActionType::Continue,
-        _ => ActionType::New,
-    };
-    let afk_message = match args.is_empty() {
-        true => None,
-        false => Some(args.to_string()),
-    };
-    let event = begin_event(conn, user, EventType::Work, action_type, afk_message)?;
-    cache.cache_afk_event_id(user.id as i64, true, event.id);
-    helpers::send_text_message(
-        api,
-        message.chat.id,
-        settings
-            .commands
-            .work
-            .work_text
-            .clone()
-            .unwrap_or_else(|| "Have a good one, king.".into()),
-        Some(message.message_id),
-    )
+pub struct Work {
+    text: Option<String>,
+}
+
+impl Command for Work {
+    fn init(settings: &Settings) -> Result<Self, ConfigError> {
+        let settings: CommandSettings = settings.command_settings("work")?;
+
+        Ok(Self {
+            text: settings.work_text,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "work"
+    }
+
+    fn description(&self) -> &'static str {
+        "Yeah but work is like a 3rd-party thing..."
+    }
+
+    fn chat_action(&self) -> Option<ChatAction> {
+        Some(ChatAction::Typing)
+    }
+
+    fn args(&self) -> &'static [Arg] {
+        ARGS
+    }
+
+    fn examples(&self) -> &'static [&'static str] {
+        &["/work", "/work meetings all day", "/work rafk"]
+    }
+
+    fn handle(
+        &self,
+        CommandParams {
+            api,
+            conn,
+            cache,
+            message,
+            args,
+            locale,
+            ..
+        }: CommandParams,
+    ) -> CommandResult<HandleUpdateError> {
+        let user = helpers::get_sender(message)?;
+        let action_type = match args.flag("rafk") {
+            true => ActionType::Continue,
+            false => ActionType::New,
+        };
+        let afk_message = args.text("message").map(String::from);
+        let event = begin_event(conn, user, EventType::Work, action_type, afk_message)?;
+        cache.cache_afk_event_id(user.id as i64, true, event.id);
+        helpers::send_text_message(
+            api,
+            message.chat.id,
+            self.text
+                .clone()
+                .unwrap_or_else(|| locale.text("work.work_text")),
+            Some(message.message_id),
+        )
+    }
 }
@@ -8,13 +8,22 @@ pub enum HandleUpdateError {
     Command(String),
     Skip,
     Api(frankenstein::Error),
+    // Failures of the external services, e.g. OpenWeather
     Service(Box<dyn Error>),
     NotAllowed {
         chat_id: i64,
         reason: String,
         chat_name: String,
         chat_type: String,
+        // The owners haven't decided about the chat yet, so the bot stays in it
+        pending: bool,
     },
+    // The command has to be called in reply to a message, the [nouns] text of what
+    // the message should contain, e.g. "location_message"
+    MissingReply(&'static str),
+    BadArgument(String),
+    // The [nouns] text of what couldn't be found, e.g. "user"
+    NotFound(String),
 }
 
 impl fmt::Display for HandleUpdateError {
@@ -36,11 +45,15 @@ impl fmt::Display for HandleUpdateError {
                 ref reason,
                 ref chat_name,
                 ref chat_type,
+                ..
             } => write!(
                 f,
                 "Chat ({}) not allowed (disallowed by {}): {} ({})",
                 chat_type, reason, chat_id, chat_name,
             ),
+            Self::MissingReply(expected) => write!(f, "Expected a reply to {}", expected),
+            Self::BadArgument(ref err) => write!(f, "Bad argument: {}", err),
+            Self::NotFound(ref what) => write!(f, "Not found: {}", what),
         }
     }
 }
@@ -1,7 +1,8 @@
 use std::convert::TryFrom;
 
-use frankenstein::{Api, ChatId, Message, SendMessageParams, TelegramApi};
+use frankenstein::{ChatId, Message, SendMessageParams, Update, User};
 
+use crate::client::Client;
 use crate::commands::CommandResult;
 use crate::errors::HandleUpdateError;
 
@@ -16,8 +17,46 @@ pub fn get_user_id_by_message(message: &Message) -> Result<i64, HandleUpdateErro
     .map_err(|e| HandleUpdateError::Command(e.to_string()))
 }
 
+// from is empty e.g. in the channel posts, the commands can't tell who has called them then
+pub fn get_sender(message: &Message) -> Result<&User, HandleUpdateError> {
+    message.from.as_ref().ok_or(HandleUpdateError::Skip)
+}
+
+pub fn get_chat_id_by_update(update: &Update) -> Option<i64> {
+    update
+        .message
+        .as_ref()
+        .or_else(|| update.edited_message.as_ref())
+        .or_else(|| update.callback_query.as_ref()?.message.as_ref())
+        .map(|message| message.chat.id)
+}
+
+pub fn get_update_type(update: &Update) -> &'static str {
+    if update.message.is_some() {
+        "message"
+    } else if update.edited_message.is_some() {
+        "edited_message"
+    } else if update.callback_query.is_some() {
+        "callback_query"
+    } else if update.inline_query.is_some() {
+        "inline_query"
+    } else {
+        "other"
+    }
+}
+
+pub fn get_user_by_update(update: &Update) -> Option<&User> {
+    update
+        .message
+        .as_ref()
+        .or_else(|| update.edited_message.as_ref())
+        .and_then(|message| message.from.as_ref())
+        .or_else(|| update.callback_query.as_ref().map(|query| &query.from))
+        .or_else(|| update.inline_query.as_ref().map(|query| &query.from))
+}
+
 pub fn send_text_message(
-    api: &Api,
+    api: &dyn Client,
     chat_id: i64,
     text: String,
     reply_to_message_id: Option<i32>,
@@ -26,6 +65,5 @@ pub fn send_text_message(
     send_message_params.set_reply_to_message_id(reply_to_message_id);
 
     api.send_message(&send_message_params)
-        .map(|_| ())
         .map_err(HandleUpdateError::Api)
 }
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use config::{Config, File, FileFormat};
+use frankenstein::User;
+use lazy_static::lazy_static;
+use liquid::{Object, Template};
+
+// Has every text, the other bundles fall back to it
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+// language -> bundle, the bundles are built into the binary
+const BUNDLES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.toml")),
+    ("ru", include_str!("../locales/ru.toml")),
+];
+
+lazy_static! {
+    static ref LOCALES: HashMap<&'static str, Locale> = BUNDLES
+        .iter()
+        .map(|&(language, source)| {
+            let locale = Locale::parse(language, source).unwrap_or_else(|err| {
+                panic!("There's an error in the {} bundle! {}", language, err)
+            });
+            (language, locale)
+        })
+        .collect();
+}
+
+// The texts of a language, e.g. locale.text("up.text"), the keys are "<section>.<name>"
+pub struct Locale {
+    language: &'static str,
+    templates: HashMap<String, Template>,
+}
+
+impl Locale {
+    fn parse(language: &'static str, source: &str) -> Result<Self, String> {
+        let mut bundle = Config::default();
+        bundle
+            .merge(File::from_str(source, FileFormat::Toml))
+            .map_err(|e| e.to_string())?;
+        let sections: HashMap<String, HashMap<String, String>> =
+            bundle.try_into().map_err(|e| e.to_string())?;
+
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let mut templates = HashMap::new();
+        for (section, texts) in sections {
+            for (name, text) in texts {
+                let key = format!("{}.{}", section, name);
+                let template = parser
+                    .parse(text.as_str())
+                    .map_err(|e| format!("{}: {}", key, e))?;
+                templates.insert(key, template);
+            }
+        }
+
+        Ok(Self {
+            language,
+            templates,
+        })
+    }
+
+    pub fn language(&self) -> &'static str {
+        self.language
+    }
+
+    // None if none of the bundles have the text, e.g. for the descriptions of the commands
+    pub fn find(&self, key: &str) -> Option<&Template> {
+        self.templates
+            .get(key)
+            .or_else(|| LOCALES[DEFAULT_LANGUAGE].templates.get(key))
+    }
+
+    pub fn template(&self, key: &str) -> &Template {
+        self.find(key)
+            .unwrap_or_else(|| panic!("There's no {} text in the bundles", key))
+    }
+
+    pub fn render(&self, key: &str, globals: &Object) -> String {
+        self.template(key)
+            .render(globals)
+            .expect("Failed to render a template")
+    }
+
+    pub fn text(&self, key: &str) -> String {
+        self.render(key, &Object::new())
+    }
+}
+
+pub fn is_supported(language: &str) -> bool {
+    LOCALES.contains_key(language)
+}
+
+// Sorted, so the lists are the same every time
+pub fn languages() -> Vec<&'static str> {
+    let mut languages: Vec<&'static str> = LOCALES.keys().copied().collect();
+    languages.sort_unstable();
+
+    languages
+}
+
+// e.g. "en (English), ru (Русский)"
+pub fn language_list() -> String {
+    languages()
+        .into_iter()
+        .map(|language| format!("{} ({})", language, locale(language).text("language.name")))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// The default language is used for the ones without a bundle
+pub fn locale(language: &str) -> &'static Locale {
+    LOCALES
+        .get(language)
+        .unwrap_or_else(|| &LOCALES[DEFAULT_LANGUAGE])
+}
+
+// Telegram sends IETF language tags, e.g. "en-US", the bundles are per language
+fn find_locale(language_code: &str) -> Option<&'static Locale> {
+    let language = language_code
+        .split('-')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    LOCALES.get(language.as_str())
+}
+
+// The language the user has chosen with /language, then the one of their Telegram app,
+// then the default one from the settings
+pub fn resolve(
+    chosen: Option<&str>,
+    user: Option<&User>,
+    default_language: &str,
+) -> &'static Locale {
+    chosen
+        .and_then(find_locale)
+        .or_else(|| find_locale(user?.language_code.as_ref()?))
+        .unwrap_or_else(|| locale(default_language))
+}
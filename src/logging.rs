@@ -0,0 +1,15 @@
+use tracing_subscriber::EnvFilter;
+
+use crate::settings::LogSettings;
+
+// RUST_LOG takes precedence over [log].level, so the verbosity can be changed for a single run
+pub fn init(settings: &LogSettings) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(settings.level.as_deref().unwrap_or("info")));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match settings.json {
+        true => builder.json().init(),
+        false => builder.init(),
+    }
+}
@@ -1,99 +1,289 @@
-use std::collections::HashMap;
 use std::error::Error;
 use std::process::exit;
-use std::thread::sleep;
-use std::time::Duration;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
 
-use frankenstein::{Api, ChatId, GetUpdatesParams, LeaveChatParams, TelegramApi, Update};
+use frankenstein::{Api, DeleteWebhookParams, GetUpdatesParams, SetWebhookParams, Update};
+use tracing::{error, info, warn};
 
+use crate::backoff::Backoff;
 use crate::cache::Cache;
+use crate::cli::Subcommand;
+use crate::client::rate_limited::RateLimitedClient;
+use crate::client::{Client, SecretWebhookParams};
 use crate::commands::{
-    donate, gn, rafk, set_my_location, set_paying_status, shuffle, up, weather, work,
+    allow_chat, cancel, donate, enable, gn, grant, help, language, list_commands, rafk,
+    set_my_location, set_paying_status, set_text, shuffle, up, weather, work, CommandsExecutor,
 };
 use crate::errors::HandleUpdateError;
-use crate::settings::Settings;
+use crate::middleware::{
+    afk::Afk, allowed_chats::AllowedChats, cooldown::Cooldown, error_replies::ErrorReplies,
+    logging::Logging, Middlewares,
+};
+use crate::services::weather::OpenWeather;
+use crate::settings::{PollingSettings, Settings, WebhookSettings};
+use crate::shutdown::Shutdown;
 use crate::updates::UpdateHandler;
+use crate::webhook::WebhookServer;
+use crate::workers::WorkerPool;
 
+mod backoff;
 mod cache;
+mod cli;
+mod client;
 mod commands;
 mod errors;
 mod helpers;
+mod i18n;
+mod logging;
+mod metrics;
+mod middleware;
+mod reload;
 mod schema;
 mod services;
 mod settings;
+mod shutdown;
 mod updates;
+mod webhook;
+mod workers;
+
+#[cfg(test)]
+mod testing;
 
-fn handle_updates(updates: Vec<Update>, handler: &mut UpdateHandler) -> Option<u32> {
+fn handle_updates(
+    updates: Vec<Update>,
+    handler: &UpdateHandler,
+    workers: &WorkerPool,
+) -> Option<u32> {
     let mut last_update_id: Option<u32> = None;
 
-    let mut chats_to_leave: HashMap<i64, ()> = HashMap::new();
-
-    for update in updates {
-        if let Err(err) = handler.handle_update(&update) {
-            println!("Error: {}", err);
-            if let HandleUpdateError::NotAllowed {
-                chat_id, chat_type, ..
-            } = err
-            {
-                if chat_type.as_str() != "private" {
-                    chats_to_leave.insert(chat_id, ());
-                }
-            }
-        }
-        last_update_id = Some(update.update_id);
-    }
+    let pending: Vec<(u32, Receiver<()>)> = updates
+        .into_iter()
+        .map(|update| (update.update_id, workers.dispatch(update)))
+        .collect();
 
-    for (chat_id, _) in chats_to_leave.into_iter() {
-        if let Err(err) = handler
-            .api
-            .leave_chat(&LeaveChatParams::new(ChatId::Integer(chat_id)))
-            .map(|response| println!("Left: {}", response.result))
-            .map_err(HandleUpdateError::from)
-        {
-            println!("Failed to leave the chat: {}", err);
+    for (update_id, done) in pending {
+        if done.recv().is_err() {
+            // The worker is gone, so the update and the rest of the batch are left to be
+            // fetched again instead of being committed as handled
+            error!("Update {} wasn't handled", update_id);
+            break;
         }
+        // The updates are awaited in order, so everything up to this one is already handled, and
+        // a restarted bot resumes right after it instead of re-handling the whole batch
+        handler.save_update_id(update_id);
+        last_update_id = Some(update_id);
     }
 
     last_update_id
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let settings = Settings::new().unwrap_or_else(|err| {
-        println!("Couldn't parse the config! {}", err);
-        exit(1);
-    });
-    let api = Api::new(settings.token.as_str());
-    let cache = Cache::new();
-
-    let mut handler = UpdateHandler::new(&api, &settings, &cache);
-    handler.commands_executor.register(up::UP);
-    handler.commands_executor.register(donate::DONATE);
-    handler
-        .commands_executor
-        .register(set_paying_status::SET_PAYING_STATUS);
-    handler.commands_executor.register(weather::WEATHER);
-    handler
-        .commands_executor
-        .register(set_my_location::SET_MY_LOCATION);
-    handler.commands_executor.register(gn::GOOD_NIGHT);
-    handler.commands_executor.register(shuffle::SHUFFLE);
-    handler.commands_executor.register(work::WORK);
-    handler.commands_executor.register(rafk::RAFK);
-    handler.send_my_commands();
+fn delete_webhook(api: &dyn Client) {
+    if let Err(err) = api
+        .delete_webhook(&DeleteWebhookParams::new())
+        .map_err(HandleUpdateError::from)
+    {
+        error!("Failed to delete the webhook: {}", err);
+    }
+}
+
+fn run_polling(
+    api: &dyn Client,
+    handler: &UpdateHandler,
+    workers: &WorkerPool,
+    polling: &PollingSettings,
+    shutdown: &Shutdown,
+) {
+    // getUpdates doesn't work while a webhook is set, e.g. after switching from the webhook mode
+    delete_webhook(api);
 
     let mut update_params = GetUpdatesParams::new();
-    update_params.set_allowed_updates(Some(vec!["message".to_string()]));
+    update_params.set_allowed_updates(Some(handler.allowed_updates()));
+    update_params.set_timeout(Some(polling.timeout()));
+    update_params.set_offset(handler.last_update_id().map(|update_id| update_id + 1));
+
+    let mut backoff = Backoff::new(polling.max_backoff());
 
-    loop {
+    // The long polling request isn't interrupted on shutdown, the shutdown waits up to
+    // [polling] timeout for it. The previous batch is already handled by then
+    while !shutdown.is_requested() {
         let result = api.get_updates(&update_params);
+        if shutdown.is_requested() {
+            // The offset is not committed, so Telegram will send these updates again after restart
+            break;
+        }
+
         match result {
-            Ok(response) => {
-                if let Some(last_update_id) = handle_updates(response.result, &mut handler) {
+            Ok(updates) => {
+                backoff.reset();
+                if let Some(last_update_id) = handle_updates(updates, handler, workers) {
                     update_params.set_offset(Some(last_update_id + 1))
                 }
             }
-            Err(error) => println!("Error: {:?}", error),
+            Err(error) => {
+                let delay = backoff.next_delay();
+                error!(
+                    "Failed to get updates: {:?}, retrying in {:?}",
+                    error, delay
+                );
+                shutdown.sleep(delay);
+            }
         };
-        sleep(Duration::new(3, 0));
     }
 }
+
+fn run_webhook(
+    api: &dyn Client,
+    handler: &UpdateHandler,
+    workers: &WorkerPool,
+    webhook: &WebhookSettings,
+    shutdown: &Shutdown,
+) {
+    let server = Arc::new(
+        WebhookServer::bind(webhook.listen.as_str(), webhook.secret_token.as_str()).unwrap_or_else(
+            |err| {
+                error!("Failed to start the webhook server: {}", err);
+                exit(1);
+            },
+        ),
+    );
+    info!("Listening for webhook updates on {}", server.server_addr());
+
+    let unblocked_server = Arc::clone(&server);
+    shutdown.on_request(move || unblocked_server.unblock());
+
+    let mut webhook_params = SetWebhookParams::new(webhook.url.clone());
+    webhook_params.set_allowed_updates(Some(handler.allowed_updates()));
+
+    if let Err(err) = api
+        .set_webhook(&SecretWebhookParams {
+            params: &webhook_params,
+            secret_token: webhook.secret_token.as_str(),
+        })
+        .map_err(HandleUpdateError::from)
+    {
+        error!("Failed to set the webhook: {}", err);
+        exit(1);
+    }
+
+    // Telegram keeps track of the delivered webhook updates itself, so there's no need to wait
+    // for the update to be handled and save the offset
+    while let Some(update) = server.recv() {
+        workers.dispatch(update);
+    }
+
+    delete_webhook(api);
+}
+
+fn register_commands(executor: &mut CommandsExecutor) {
+    executor.register::<up::Up>();
+    executor.register::<donate::Donate>();
+    executor.register::<set_paying_status::SetPayingStatus>();
+    executor.register::<weather::Weather>();
+    executor.register::<set_my_location::SetMyLocation>();
+    executor.register::<gn::GoodNight>();
+    executor.register::<shuffle::Shuffle>();
+    executor.register::<work::Work>();
+    executor.register::<rafk::Rafk>();
+    executor.register::<enable::Enable>();
+    executor.register::<enable::Disable>();
+    executor.register::<list_commands::ListCommands>();
+    executor.register::<grant::Grant>();
+    executor.register::<grant::Revoke>();
+    executor.register::<help::Help>();
+    executor.register::<cancel::Cancel>();
+    executor.register::<set_text::SetText>();
+    executor.register::<language::Language>();
+    executor.register::<allow_chat::AllowChat>();
+    executor.register::<allow_chat::DenyChat>();
+}
+
+// The order matters: the logging middleware sees the chats which aren't allowed, and
+// the AFK events aren't ended by the messages from them
+fn register_middlewares(middlewares: &mut Middlewares) {
+    middlewares.register(Logging);
+    middlewares.register(ErrorReplies);
+    middlewares.register(AllowedChats);
+    middlewares.register(Afk);
+    middlewares.register(Cooldown);
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    match Subcommand::from_args() {
+        Ok(Subcommand::Run) => {}
+        Ok(Subcommand::CheckConfig) => exit(cli::check_config(register_commands)),
+        Ok(Subcommand::PrintDefaultConfig) => {
+            cli::print_default_config();
+            return Ok(());
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(2);
+        }
+    }
+
+    let settings = Arc::new(Settings::new().unwrap_or_else(|err| {
+        // The logger is configured by the settings, so there's nothing to log with yet
+        eprintln!("Couldn't parse the config! {}", err);
+        exit(1);
+    }));
+    logging::init(&settings.log);
+    for setting in settings.shadowed_texts() {
+        warn!("{}", settings::shadowed_text_warning(setting.as_str()));
+    }
+
+    let shutdown = Shutdown::listen().unwrap_or_else(|err| {
+        error!("Failed to listen for signals: {}", err);
+        exit(1);
+    });
+
+    if let Some(metrics_settings) = settings.metrics.as_ref() {
+        if let Err(err) = metrics::serve(metrics_settings.listen.as_str(), &shutdown) {
+            error!("Failed to start the metrics server: {}", err);
+            exit(1);
+        }
+    }
+
+    let api: Arc<dyn Client> = Arc::new(RateLimitedClient::new(
+        Api::new(settings.token.as_str()),
+        &settings.rate_limit,
+    ));
+    let cache = Arc::new(Cache::new());
+
+    let mut handler = UpdateHandler::new(
+        Arc::clone(&api),
+        Arc::new(OpenWeather),
+        Arc::clone(&settings),
+        cache,
+        register_commands,
+    );
+    register_middlewares(&mut handler.middlewares);
+    if let Err(err) = handler.send_my_commands() {
+        error!("Failed to set my commands: {}", err);
+        exit(1);
+    }
+
+    let handler = Arc::new(handler);
+    if let Err(err) = reload::listen(Arc::clone(&handler)) {
+        error!("Failed to listen for SIGHUP: {}", err);
+        exit(1);
+    }
+    let workers = WorkerPool::new(settings.workers(), Arc::clone(&handler));
+
+    match settings.webhook.as_ref() {
+        Some(webhook) => run_webhook(api.as_ref(), &handler, &workers, webhook, &shutdown),
+        None => run_polling(
+            api.as_ref(),
+            &handler,
+            &workers,
+            &settings.polling,
+            &shutdown,
+        ),
+    };
+
+    info!("Waiting for the updates in progress to be handled...");
+    workers.shutdown();
+    info!("Shut down gracefully");
+
+    Ok(())
+}
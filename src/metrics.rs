@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::thread;
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    Encoder, Histogram, HistogramVec, IntCounterVec, IntGauge, TextEncoder,
+};
+use tiny_http::{Header, Response, Server};
+use tracing::{error, info};
+
+use crate::shutdown::Shutdown;
+
+lazy_static! {
+    pub static ref UPDATES: IntCounterVec = register_int_counter_vec!(
+        "maldness_bot_updates_total",
+        "Processed updates by type",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref COMMAND_INVOCATIONS: IntCounterVec = register_int_counter_vec!(
+        "maldness_bot_command_invocations_total",
+        "Command invocations by command name",
+        &["command"]
+    )
+    .unwrap();
+    pub static ref COMMAND_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "maldness_bot_command_failures_total",
+        "Failed command invocations by command name",
+        &["command"]
+    )
+    .unwrap();
+    pub static ref OPENWEATHER_REQUEST_DURATION: Histogram = register_histogram!(
+        "maldness_bot_openweather_request_duration_seconds",
+        "OpenWeather API request latency"
+    )
+    .unwrap();
+    pub static ref OPENWEATHER_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "maldness_bot_openweather_errors_total",
+        "Failed OpenWeather API requests by error kind",
+        &["kind"]
+    )
+    .unwrap();
+    pub static ref DB_QUERY_DURATION: HistogramVec = register_histogram_vec!(
+        "maldness_bot_db_query_duration_seconds",
+        "Database query latency by service function",
+        &["query"]
+    )
+    .unwrap();
+    pub static ref AFK_USERS: IntGauge =
+        register_int_gauge!("maldness_bot_afk_users", "Number of users currently AFK").unwrap();
+    pub static ref OUTGOING_QUEUE_DEPTH: IntGauge = register_int_gauge!(
+        "maldness_bot_outgoing_queue_depth",
+        "Number of outgoing messages waiting for the rate limiter"
+    )
+    .unwrap();
+}
+
+pub fn time_db_query<T>(query: &str, f: impl FnOnce() -> T) -> T {
+    let _timer = DB_QUERY_DURATION.with_label_values(&[query]).start_timer();
+
+    f()
+}
+
+fn render() -> Result<Vec<u8>, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok(buffer)
+}
+
+// Serves the metrics at /metrics in a background thread until the shutdown is requested
+pub fn serve(listen: &str, shutdown: &Shutdown) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let server = Arc::new(Server::http(listen)?);
+    info!("Serving metrics on http://{}/metrics", server.server_addr());
+
+    let unblocked_server = Arc::clone(&server);
+    shutdown.on_request(move || unblocked_server.unblock());
+
+    thread::Builder::new()
+        .name("metrics".into())
+        .spawn(move || {
+            for request in server.incoming_requests() {
+                if request.url() != "/metrics" {
+                    let _ = request.respond(Response::empty(404));
+                    continue;
+                }
+
+                let result = match render() {
+                    Ok(body) => request.respond(
+                        Response::from_data(body).with_header(
+                            "Content-Type: text/plain; version=0.0.4"
+                                .parse::<Header>()
+                                .unwrap(),
+                        ),
+                    ),
+                    Err(err) => {
+                        error!("Failed to render the metrics: {}", err);
+                        request.respond(Response::empty(500))
+                    }
+                };
+
+                if let Err(err) = result {
+                    error!("Failed to respond to the metrics request: {}", err);
+                }
+            }
+        })?;
+
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use diesel::PgConnection;
+use frankenstein::{Message, Update};
+
+use crate::cache::Cache;
+use crate::client::Client;
+use crate::commands::args::Args;
+use crate::commands::{Command, CommandsExecutor};
+use crate::errors::HandleUpdateError;
+use crate::i18n::Locale;
+use crate::settings::Settings;
+
+pub mod afk;
+pub mod allowed_chats;
+pub mod cooldown;
+pub mod error_replies;
+pub mod logging;
+
+pub type MiddlewareResult = Result<(), HandleUpdateError>;
+
+pub enum Flow {
+    Continue,
+    // The update or the command is considered handled, the rest of the chain is skipped
+    Stop,
+}
+
+// Values the middlewares pass to each other, one per type, e.g. when a command was started
+#[derive(Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Extensions {
+    pub fn insert<T: Any>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+}
+
+pub struct UpdateContext<'a> {
+    pub api: &'a dyn Client,
+    pub conn: &'a mut PgConnection,
+    pub cache: &'a Cache,
+    pub settings: &'a Settings,
+    pub commands_executor: &'a CommandsExecutor,
+    pub update: &'a Update,
+    // The language of the user the update is from
+    pub locale: &'static Locale,
+    pub extensions: Extensions,
+}
+
+// Created once the command is found, the caller is allowed to call it and the arguments are parsed
+pub struct CommandContext<'a> {
+    pub api: &'a dyn Client,
+    pub conn: &'a mut PgConnection,
+    pub cache: &'a Cache,
+    pub settings: &'a Settings,
+    pub command: &'a dyn Command,
+    pub message: &'a Message,
+    pub args: Args,
+    pub locale: &'static Locale,
+    pub extensions: &'a mut Extensions,
+}
+
+// Runs around every update and every command. The before hooks run in the registration order
+// and can stop the chain, the after hooks run in the reverse order and see the result,
+// but only for the middlewares whose before hook was called.
+pub trait Middleware: Send + Sync {
+    fn before_update(&self, _ctx: &mut UpdateContext) -> Result<Flow, HandleUpdateError> {
+        Ok(Flow::Continue)
+    }
+
+    fn after_update(&self, _ctx: &mut UpdateContext, _result: &MiddlewareResult) {}
+
+    fn before_command(&self, _ctx: &mut CommandContext) -> Result<Flow, HandleUpdateError> {
+        Ok(Flow::Continue)
+    }
+
+    fn after_command(&self, _ctx: &mut CommandContext, _result: &MiddlewareResult) {}
+}
+
+#[derive(Default)]
+pub struct Middlewares {
+    middlewares: Vec<Box<dyn Middleware>>,
+}
+
+impl Middlewares {
+    pub fn register<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.middlewares.push(Box::new(middleware));
+    }
+
+    fn run<C>(
+        &self,
+        ctx: &mut C,
+        before: impl Fn(&dyn Middleware, &mut C) -> Result<Flow, HandleUpdateError>,
+        after: impl Fn(&dyn Middleware, &mut C, &MiddlewareResult),
+        next: impl FnOnce(&mut C) -> MiddlewareResult,
+    ) -> MiddlewareResult {
+        run_chain(
+            &self.middlewares,
+            ctx,
+            |middleware, ctx| before(middleware.as_ref(), ctx),
+            |middleware, ctx, result| after(middleware.as_ref(), ctx, result),
+            next,
+        )
+    }
+
+    pub fn run_update(
+        &self,
+        ctx: &mut UpdateContext,
+        next: impl FnOnce(&mut UpdateContext) -> MiddlewareResult,
+    ) -> MiddlewareResult {
+        self.run(
+            ctx,
+            |middleware, ctx| middleware.before_update(ctx),
+            |middleware, ctx, result| middleware.after_update(ctx, result),
+            next,
+        )
+    }
+
+    pub fn run_command(
+        &self,
+        ctx: &mut CommandContext,
+        next: impl FnOnce(&mut CommandContext) -> MiddlewareResult,
+    ) -> MiddlewareResult {
+        self.run(
+            ctx,
+            |middleware, ctx| middleware.before_command(ctx),
+            |middleware, ctx, result| middleware.after_command(ctx, result),
+            next,
+        )
+    }
+}
+
+// Runs the before hooks in order until one of them stops the chain, then next() if none did,
+// then the after hooks of the entered links in the reverse order
+fn run_chain<M, C>(
+    chain: &[M],
+    ctx: &mut C,
+    before: impl Fn(&M, &mut C) -> Result<Flow, HandleUpdateError>,
+    after: impl Fn(&M, &mut C, &MiddlewareResult),
+    next: impl FnOnce(&mut C) -> MiddlewareResult,
+) -> MiddlewareResult {
+    let mut entered = 0;
+    let mut result = None;
+
+    for link in chain.iter() {
+        entered += 1;
+        match before(link, ctx) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Stop) => {
+                result = Some(Ok(()));
+                break;
+            }
+            Err(err) => {
+                result = Some(Err(err));
+                break;
+            }
+        }
+    }
+
+    let result = result.unwrap_or_else(|| next(ctx));
+    for link in chain[..entered].iter().rev() {
+        after(link, ctx, &result);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A link of the chain which logs its hooks, before() returns what the link is given
+    struct Link {
+        name: &'static str,
+        before: fn() -> Result<Flow, HandleUpdateError>,
+    }
+
+    fn link(name: &'static str) -> Link {
+        Link {
+            name,
+            before: || Ok(Flow::Continue),
+        }
+    }
+
+    fn run(chain: &[Link]) -> (Vec<String>, MiddlewareResult) {
+        let mut log = Vec::new();
+        let result = run_chain(
+            chain,
+            &mut log,
+            |link, log| {
+                log.push(format!("before {}", link.name));
+                (link.before)()
+            },
+            |link, log, result| log.push(format!("after {} {}", link.name, result.is_ok())),
+            |log| {
+                log.push("next".to_string());
+                Err(HandleUpdateError::Skip)
+            },
+        );
+
+        (log, result)
+    }
+
+    #[test]
+    fn runs_before_in_order_and_after_in_reverse() {
+        let (log, result) = run(&[link("logging"), link("errors"), link("afk")]);
+
+        assert_eq!(
+            log,
+            vec![
+                "before logging",
+                "before errors",
+                "before afk",
+                "next",
+                "after afk false",
+                "after errors false",
+                "after logging false",
+            ]
+        );
+        assert!(matches!(result, Err(HandleUpdateError::Skip)));
+    }
+
+    #[test]
+    fn stop_skips_rest_of_chain() {
+        let stop = Link {
+            name: "allowed",
+            before: || Ok(Flow::Stop),
+        };
+        let (log, result) = run(&[link("logging"), stop, link("afk")]);
+
+        assert_eq!(
+            log,
+            vec![
+                "before logging",
+                "before allowed",
+                "after allowed true",
+                "after logging true",
+            ]
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn error_skips_rest_of_chain() {
+        let fail = Link {
+            name: "allowed",
+            before: || Err(HandleUpdateError::Command("failed".to_string())),
+        };
+        let (log, result) = run(&[link("logging"), fail, link("afk")]);
+
+        assert_eq!(
+            log,
+            vec![
+                "before logging",
+                "before allowed",
+                "after allowed false",
+                "after logging false",
+            ]
+        );
+        assert!(matches!(result, Err(HandleUpdateError::Command(_))));
+    }
+
+    #[test]
+    fn empty_chain_runs_next() {
+        let (log, _) = run(&[]);
+
+        assert_eq!(log, vec!["next"]);
+    }
+}
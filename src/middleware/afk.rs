@@ -0,0 +1,119 @@
+use tracing::error;
+
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::middleware::{Flow, Middleware, UpdateContext};
+use crate::services::afk_event::errors::ServiceError;
+use crate::services::afk_event::functions::end_event;
+use crate::services::chat_text::functions::get_chat_template;
+
+// Any new message from an AFK user ends their AFK event. Edits don't, otherwise editing
+// the /gn message would wake the user up right away.
+pub struct Afk;
+
+impl Middleware for Afk {
+    fn before_update(&self, ctx: &mut UpdateContext) -> Result<Flow, HandleUpdateError> {
+        let message = match ctx.update.message.as_ref() {
+            Some(message) => message,
+            None => return Ok(Flow::Continue),
+        };
+        // e.g. the posts of the channels linked to the chat
+        if message.from.is_none() {
+            return Ok(Flow::Continue);
+        }
+        let user_id = helpers::get_user_id_by_message(message)?;
+
+        if let Some(event_id) = ctx.cache.get_afk_event_id(user_id) {
+            match end_event(ctx.conn, event_id) {
+                Ok(event) => {
+                    // The event has ended already, so the reply is sent anyway
+                    let chat_template =
+                        get_chat_template(ctx.conn, ctx.cache, message.chat.id, event.chat_text())
+                            .unwrap_or_else(|err| {
+                                error!(
+                                    "Failed to get the {} template of chat {}: {}",
+                                    event.chat_text().key(),
+                                    message.chat.id,
+                                    err
+                                );
+                                None
+                            });
+                    let _ = helpers::send_text_message(
+                        ctx.api,
+                        message.chat.id,
+                        event.to_string(
+                            ctx.settings,
+                            ctx.locale,
+                            chat_template.as_deref(),
+                            message,
+                        ),
+                        Some(message.message_id),
+                    );
+                }
+                Err(ServiceError::NotFound) => {}
+                Err(err) => error!("Failed to end afk event for user {}: {}", user_id, err),
+            };
+            ctx.cache.cache_afk_event_id(user_id, false, event_id);
+        }
+
+        Ok(Flow::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{group, message, random_id, update, user, TestBot};
+
+    #[test]
+    #[ignore]
+    fn message_ends_afk_event() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let user_id = random_id();
+        let alice = user(user_id, "alice");
+
+        bot.send(update("message", message(1, &chat, &alice, "/gn bye")));
+        assert!(bot.cache.get_afk_event_id(user_id).is_some());
+
+        // Editing the /gn message doesn't wake the user up
+        let mut edited = message(1, &chat, &alice, "/gn bye!");
+        edited["edit_date"] = 1_627_000_001.into();
+        bot.send(update("edited_message", edited));
+        assert!(bot.cache.get_afk_event_id(user_id).is_some());
+
+        bot.send(update("message", message(2, &chat, &alice, "morning")));
+        assert!(bot.cache.get_afk_event_id(user_id).is_none());
+
+        // Only the first message is greeted
+        bot.send(update("message", message(3, &chat, &alice, "anyone here?")));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, "Good night!".to_string(), Some(1)),
+                (chat_id, "alice is awake: bye".to_string(), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn messages_of_other_users_keep_afk_event() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let user_id = random_id();
+
+        bot.send(update(
+            "message",
+            message(1, &chat, &user(user_id, "alice"), "/gn"),
+        ));
+        bot.send(update(
+            "message",
+            message(2, &chat, &user(random_id(), "bob"), "good night"),
+        ));
+
+        assert!(bot.cache.get_afk_event_id(user_id).is_some());
+        assert_eq!(bot.replies().len(), 1);
+    }
+}
@@ -0,0 +1,143 @@
+use diesel::PgConnection;
+
+use crate::cache::Cache;
+use crate::commands::allow_chat::send_join_request;
+use crate::errors::HandleUpdateError;
+use crate::middleware::{Flow, Middleware, UpdateContext};
+use crate::services::chat_permission::functions::{get_permission, request_permission};
+use crate::services::chat_permission::ChatPermission;
+
+// Stops the updates from the chats the owners or [allowed_chats] don't allow, the bot leaves them
+// afterwards. The owners are asked about the unknown groups, the bot stays in them until then
+pub struct AllowedChats;
+
+impl AllowedChats {
+    fn permission(
+        conn: &mut PgConnection,
+        cache: &Cache,
+        chat_id: i64,
+    ) -> Result<Option<ChatPermission>, HandleUpdateError> {
+        if let Some(permission) = cache.get_chat_permission(chat_id) {
+            return Ok(permission);
+        }
+
+        let permission = get_permission(conn, chat_id)?;
+        cache.cache_chat_permission(chat_id, permission);
+
+        Ok(permission)
+    }
+}
+
+impl Middleware for AllowedChats {
+    fn before_update(&self, ctx: &mut UpdateContext) -> Result<Flow, HandleUpdateError> {
+        // The message of a callback query is the bot's, e.g. the owners press the buttons of
+        // the join requests in their private chats
+        if let Some(query) = ctx.update.callback_query.as_ref() {
+            if ctx.settings.is_admin(query.from.id) {
+                return Ok(Flow::Continue);
+            }
+        }
+
+        let message = match ctx
+            .update
+            .message
+            .as_ref()
+            .or_else(|| ctx.update.edited_message.as_ref())
+            .or_else(|| ctx.update.callback_query.as_ref()?.message.as_ref())
+        {
+            Some(message) => message,
+            // Inline queries don't come from a chat
+            None => return Ok(Flow::Continue),
+        };
+
+        let permission = Self::permission(ctx.conn, ctx.cache, message.chat.id)?;
+        let err = match ctx.settings.check_for_allowed_update(message, permission) {
+            Some(err) => err,
+            None => return Ok(Flow::Continue),
+        };
+
+        // The first update from a new group, e.g. the one about the bot being added to it.
+        // Another worker might have asked about the chat already
+        if let HandleUpdateError::NotAllowed { pending: true, .. } = err {
+            if permission.is_none() {
+                let chat_id = message.chat.id;
+                match request_permission(ctx.conn, chat_id)? {
+                    true => {
+                        ctx.cache
+                            .cache_chat_permission(chat_id, Some(ChatPermission::Pending));
+                        send_join_request(ctx.api, ctx.settings, message);
+                    }
+                    false => ctx
+                        .cache
+                        .cache_chat_permission(chat_id, get_permission(ctx.conn, chat_id)?),
+                }
+            }
+        }
+
+        Err(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{group, message, private, random_id, update, user, TestBot};
+
+    #[test]
+    #[ignore]
+    fn stops_updates_from_disallowed_group_and_leaves_it() {
+        let chat_id = random_id();
+        let bot = TestBot::new(&format!(
+            "[allowed_chats]\ngroup = {{ \"{}\" = false }}",
+            -chat_id
+        ));
+
+        bot.send(update(
+            "message",
+            message(1, &group(chat_id), &user(random_id(), "alice"), "/gn"),
+        ));
+
+        assert!(bot.replies().is_empty());
+        assert!(bot.client.chat_actions().is_empty());
+        assert_eq!(bot.client.left_chats(), vec![-chat_id]);
+    }
+
+    #[test]
+    #[ignore]
+    fn ignores_disallowed_private_chats() {
+        let user_id = random_id();
+        let bot = TestBot::new(&format!(
+            "[allowed_chats]\nprivate = {{ \"{}\" = false }}",
+            user_id
+        ));
+        let alice = user(user_id, "alice");
+
+        bot.send(update(
+            "message",
+            message(1, &private(&alice), &alice, "/gn"),
+        ));
+
+        assert!(bot.replies().is_empty());
+        assert!(bot.client.left_chats().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn lets_allowed_group_through() {
+        let chat_id = random_id();
+        let bot = TestBot::new(&format!(
+            "[allowed_chats]\nallow_unspecified = false\ngroup = {{ \"{}\" = true }}",
+            -chat_id
+        ));
+
+        bot.send(update(
+            "message",
+            message(1, &group(chat_id), &user(random_id(), "alice"), "/gn"),
+        ));
+
+        assert_eq!(
+            bot.replies(),
+            vec![(-chat_id, "Good night!".to_string(), Some(1))]
+        );
+        assert!(bot.client.left_chats().is_empty());
+    }
+}
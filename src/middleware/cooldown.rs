@@ -0,0 +1,281 @@
+use std::time::Duration;
+
+use frankenstein::User;
+use humantime::format_duration;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::cache::{Cache, CooldownKey};
+use crate::commands::Command;
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::i18n::Locale;
+use crate::middleware::{CommandContext, Flow, Middleware};
+use crate::settings::Settings;
+
+#[derive(Serialize)]
+struct CooldownGlobals {
+    command: String,
+    remaining: String,
+}
+
+// Doesn't let the commands be called again before their cooldown is over, admins are exempt
+pub struct Cooldown;
+
+impl Cooldown {
+    // Returns the time left if the command is still on cooldown for the user or the chat
+    fn start(
+        settings: &Settings,
+        cache: &Cache,
+        command: &dyn Command,
+        from: &User,
+        chat_id: i64,
+    ) -> Option<Duration> {
+        if settings.is_admin(from.id) {
+            return None;
+        }
+
+        let cooldown = command.cooldown();
+        let mut cooldowns = Vec::new();
+        if let Some(duration) = cooldown.per_user {
+            cooldowns.push((CooldownKey::User(from.id), duration));
+        }
+        if let Some(duration) = cooldown.per_chat {
+            cooldowns.push((CooldownKey::Chat(chat_id), duration));
+        }
+
+        cache.start_cooldowns(command.name(), &cooldowns)
+    }
+
+    // None in the silent mode
+    fn text(
+        settings: &Settings,
+        locale: &Locale,
+        command: &str,
+        remaining: Duration,
+    ) -> Option<String> {
+        if settings.cooldown.silent {
+            return None;
+        }
+
+        // Sub-second precision only makes the message harder to read
+        let remaining = Duration::from_secs(remaining.as_secs() + 1);
+        let globals = liquid::to_object(&CooldownGlobals {
+            command: command.to_string(),
+            remaining: format_duration(remaining).to_string(),
+        })
+        .expect("Failed to serialize CooldownGlobals to liquid::Object");
+
+        Some(
+            settings
+                .cooldown
+                .template(locale)
+                .render(&globals)
+                .expect("Failed to render a template"),
+        )
+    }
+}
+
+impl Middleware for Cooldown {
+    fn before_command(&self, ctx: &mut CommandContext) -> Result<Flow, HandleUpdateError> {
+        let from = match ctx.message.from.as_ref() {
+            Some(from) => from,
+            None => return Ok(Flow::Continue),
+        };
+        let remaining = match Self::start(
+            ctx.settings,
+            ctx.cache,
+            ctx.command,
+            from,
+            ctx.message.chat.id,
+        ) {
+            Some(remaining) => remaining,
+            None => return Ok(Flow::Continue),
+        };
+
+        debug!("Command is on cooldown for {:?}", remaining);
+        if let Some(text) = Self::text(ctx.settings, ctx.locale, ctx.command.name(), remaining) {
+            helpers::send_text_message(
+                ctx.api,
+                ctx.message.chat.id,
+                text,
+                Some(ctx.message.message_id),
+            )?;
+        }
+
+        Ok(Flow::Stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::weather::Weather;
+    use crate::i18n;
+    use crate::testing;
+
+    // The owner in testing::settings()
+    const ADMIN_ID: i64 = 2;
+
+    struct Chat {
+        settings: Settings,
+        cache: Cache,
+        command: Weather,
+    }
+
+    impl Chat {
+        fn new(config: &str) -> Self {
+            let settings = testing::settings(config);
+            let command = Weather::init(&settings).unwrap();
+
+            Self {
+                settings,
+                cache: Cache::new(),
+                command,
+            }
+        }
+
+        fn call(&self, user_id: i64, chat_id: i64) -> Option<Duration> {
+            let from: User = serde_json::from_value(testing::user(user_id, "alice")).unwrap();
+            Cooldown::start(&self.settings, &self.cache, &self.command, &from, chat_id)
+        }
+    }
+
+    #[test]
+    fn user_window() {
+        let chat = Chat::new("[commands.weather]\ncooldown_per_user = 30\ncooldown_per_chat = 0");
+
+        assert_eq!(chat.call(10, -1), None);
+        let remaining = chat.call(10, -1).unwrap();
+        assert!(remaining > Duration::from_secs(29) && remaining <= Duration::from_secs(30));
+        // In the other chats too
+        assert!(chat.call(10, -2).is_some());
+        assert_eq!(chat.call(11, -1), None);
+    }
+
+    #[test]
+    fn chat_window() {
+        let chat = Chat::new("[commands.weather]\ncooldown_per_user = 0\ncooldown_per_chat = 5");
+
+        assert_eq!(chat.call(10, -1), None);
+        let remaining = chat.call(11, -1).unwrap();
+        assert!(remaining > Duration::from_secs(4) && remaining <= Duration::from_secs(5));
+        assert_eq!(chat.call(11, -2), None);
+    }
+
+    #[test]
+    fn longest_window_is_reported() {
+        let chat = Chat::new("[commands.weather]\ncooldown_per_user = 30\ncooldown_per_chat = 5");
+
+        assert_eq!(chat.call(10, -1), None);
+        assert!(chat.call(10, -1).unwrap() > Duration::from_secs(5));
+    }
+
+    #[test]
+    fn rejected_call_does_not_restart_window() {
+        let chat = Chat::new("[commands.weather]\ncooldown_per_user = 30\ncooldown_per_chat = 5");
+
+        assert_eq!(chat.call(10, -1), None);
+        // The chat is on cooldown, so the call of another user doesn't start theirs
+        assert!(chat.call(11, -1).is_some());
+        assert_eq!(chat.call(11, -2), None);
+    }
+
+    #[test]
+    fn zero_turns_cooldown_off() {
+        let chat = Chat::new("[commands.weather]\ncooldown_per_user = 0\ncooldown_per_chat = 0");
+
+        assert_eq!(chat.call(10, -1), None);
+        assert_eq!(chat.call(10, -1), None);
+    }
+
+    #[test]
+    fn defaults_apply_without_settings() {
+        let chat = Chat::new("");
+
+        assert_eq!(chat.call(10, -1), None);
+        assert!(chat.call(10, -1).unwrap() > Duration::from_secs(5));
+    }
+
+    #[test]
+    fn admins_are_exempt() {
+        let chat = Chat::new("");
+
+        assert_eq!(chat.call(ADMIN_ID, -1), None);
+        assert_eq!(chat.call(ADMIN_ID, -1), None);
+        assert_eq!(chat.call(10, -1), None);
+    }
+
+    #[test]
+    fn remaining_time_text() {
+        let settings = testing::settings("");
+        let text = |remaining| Cooldown::text(&settings, i18n::locale("en"), "weather", remaining);
+
+        assert_eq!(
+            text(Duration::from_millis(29_400)),
+            Some("Slow down! You can use /weather again in 30s".to_string())
+        );
+        assert_eq!(
+            text(Duration::from_millis(61_200)),
+            Some("Slow down! You can use /weather again in 1m 2s".to_string())
+        );
+    }
+
+    #[test]
+    fn text_from_config() {
+        let settings = testing::settings("[cooldown]\ntext = \"Wait {{ remaining }}\"");
+
+        assert_eq!(
+            Cooldown::text(
+                &settings,
+                i18n::locale("en"),
+                "weather",
+                Duration::from_secs(4)
+            ),
+            Some("Wait 5s".to_string())
+        );
+    }
+
+    #[test]
+    fn silent_mode_sends_nothing() {
+        let settings = testing::settings("[cooldown]\nsilent = true");
+
+        assert_eq!(
+            Cooldown::text(
+                &settings,
+                i18n::locale("en"),
+                "weather",
+                Duration::from_secs(4)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn command_on_cooldown_gets_reply() {
+        let bot = testing::TestBot::new("[commands.weather]\ncooldown_per_chat = 0");
+        let chat = testing::group(testing::random_id());
+        let alice = testing::user(testing::random_id(), "alice");
+
+        bot.send(testing::update(
+            "message",
+            testing::message(1, &chat, &alice, "/weather"),
+        ));
+        bot.send(testing::update(
+            "message",
+            testing::message(2, &chat, &alice, "/weather"),
+        ));
+
+        let replies = bot.replies();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(
+            replies[1],
+            (
+                chat["id"].as_i64().unwrap(),
+                "Slow down! You can use /weather again in 30s".to_string(),
+                Some(2)
+            )
+        );
+    }
+}
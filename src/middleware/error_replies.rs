@@ -0,0 +1,223 @@
+use serde::Serialize;
+use tracing::warn;
+
+use crate::errors::HandleUpdateError;
+use crate::helpers;
+use crate::i18n::Locale;
+use crate::middleware::{Middleware, MiddlewareResult, UpdateContext};
+use crate::settings::{ErrorReply, Settings};
+
+// Shown to the user instead of the unexpected error, so the error can be found in the logs
+pub struct ErrorId(pub String);
+
+#[derive(Default, Serialize)]
+struct ErrorReplyGlobals {
+    expected: String,
+    error: String,
+    what: String,
+    error_id: String,
+}
+
+// The nouns without a text in the bundles are shown as they are
+fn noun(locale: &Locale, noun: &str) -> String {
+    match locale.find(format!("nouns.{}", noun).as_str()) {
+        Some(template) => template
+            .render(&liquid::Object::new())
+            .expect("Failed to render a template"),
+        None => noun.to_string(),
+    }
+}
+
+// Tells the user what went wrong with their message instead of failing silently
+pub struct ErrorReplies;
+
+impl ErrorReplies {
+    // None if the user isn't told about the error. The unexpected errors get an id
+    fn reply(
+        settings: &Settings,
+        locale: &Locale,
+        err: &HandleUpdateError,
+    ) -> Option<(String, Option<ErrorId>)> {
+        let mut globals = ErrorReplyGlobals::default();
+        let reply = match err {
+            HandleUpdateError::Skip | HandleUpdateError::NotAllowed { .. } => return None,
+            HandleUpdateError::MissingReply(expected) => {
+                globals.expected = noun(locale, expected);
+                ErrorReply::MissingReply
+            }
+            HandleUpdateError::BadArgument(err) => {
+                globals.error = err.clone();
+                ErrorReply::BadArgument
+            }
+            HandleUpdateError::NotFound(what) => {
+                globals.what = noun(locale, what);
+                ErrorReply::NotFound
+            }
+            HandleUpdateError::Api(_) | HandleUpdateError::Service(_) => ErrorReply::Upstream,
+            HandleUpdateError::Command(_) => {
+                globals.error_id = format!("{:08x}", rand::random::<u32>());
+                ErrorReply::Unexpected
+            }
+        };
+        let error_id = match reply {
+            ErrorReply::Unexpected => Some(ErrorId(globals.error_id.clone())),
+            _ => None,
+        };
+
+        let globals = liquid::to_object(&globals)
+            .expect("Failed to serialize ErrorReplyGlobals to liquid::Object");
+        let text = settings
+            .error_replies
+            .template(reply, locale)
+            .render(&globals)
+            .expect("Failed to render a template");
+
+        Some((text, error_id))
+    }
+}
+
+impl Middleware for ErrorReplies {
+    fn after_update(&self, ctx: &mut UpdateContext, result: &MiddlewareResult) {
+        let err = match result {
+            Ok(()) => return,
+            Err(err) => err,
+        };
+        // Only the messages can be replied to
+        let message = match ctx
+            .update
+            .message
+            .as_ref()
+            .or_else(|| ctx.update.edited_message.as_ref())
+        {
+            Some(message) => message,
+            None => return,
+        };
+
+        let (text, error_id) = match Self::reply(ctx.settings, ctx.locale, err) {
+            Some(reply) => reply,
+            None => return,
+        };
+        if let Some(error_id) = error_id {
+            ctx.extensions.insert(error_id);
+        }
+
+        if let Err(err) =
+            helpers::send_text_message(ctx.api, message.chat.id, text, Some(message.message_id))
+        {
+            warn!("Failed to reply with the error: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use frankenstein::ErrorResponse;
+    use serde_json::json;
+
+    use super::*;
+    use crate::i18n;
+    use crate::services::weather::WeatherError;
+    use crate::testing;
+
+    fn reply(config: &str, err: HandleUpdateError) -> Option<(String, Option<ErrorId>)> {
+        ErrorReplies::reply(&testing::settings(config), i18n::locale("en"), &err)
+    }
+
+    fn text(err: HandleUpdateError) -> Option<String> {
+        reply("", err).map(|(text, _)| text)
+    }
+
+    #[test]
+    fn missing_reply_names_expected_message() {
+        assert_eq!(
+            text(HandleUpdateError::MissingReply("location_message")),
+            Some("Reply to a message with a location to use this command".to_string())
+        );
+    }
+
+    #[test]
+    fn bad_argument_shows_error() {
+        assert_eq!(
+            text(HandleUpdateError::BadArgument("Unknown role".to_string())),
+            Some("Unknown role".to_string())
+        );
+    }
+
+    #[test]
+    fn not_found_names_what() {
+        assert_eq!(
+            text(HandleUpdateError::NotFound("user".to_string())),
+            Some("I couldn't find that user".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_noun_is_shown_as_is() {
+        assert_eq!(
+            text(HandleUpdateError::NotFound("chat".to_string())),
+            Some("I couldn't find that chat".to_string())
+        );
+    }
+
+    #[test]
+    fn upstream_errors() {
+        let api = frankenstein::Error::ApiError(
+            serde_json::from_value::<ErrorResponse>(json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "Bad Request",
+            }))
+            .unwrap(),
+        );
+        let upstream = Some("Something went wrong on the other side, try again later".to_string());
+
+        assert_eq!(text(HandleUpdateError::Api(api)), upstream);
+        assert_eq!(
+            text(WeatherError::Json(io::Error::new(io::ErrorKind::InvalidData, "bad JSON")).into()),
+            upstream
+        );
+    }
+
+    #[test]
+    fn unexpected_error_gets_id() {
+        let (text, error_id) = reply("", HandleUpdateError::Command("failed".to_string())).unwrap();
+        let ErrorId(error_id) = error_id.unwrap();
+
+        assert_eq!(error_id.len(), 8);
+        assert_eq!(
+            text,
+            format!(
+                "Sorry, something went wrong. Mention this error ID if you report it: {}",
+                error_id
+            )
+        );
+    }
+
+    #[test]
+    fn skipped_and_not_allowed_errors_are_silent() {
+        assert_eq!(text(HandleUpdateError::Skip), None);
+        assert_eq!(
+            text(HandleUpdateError::NotAllowed {
+                chat_id: -1,
+                reason: "configuration".to_string(),
+                chat_name: "Test".to_string(),
+                chat_type: "group".to_string(),
+                pending: false,
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn config_overrides_bundle() {
+        let (text, _) = reply(
+            "[error_replies]\nnot_found = \"No {{ what }} here\"",
+            HandleUpdateError::NotFound("user".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(text, "No user here");
+    }
+}
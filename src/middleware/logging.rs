@@ -0,0 +1,52 @@
+use std::time::Instant;
+
+use tracing::{debug, error, info};
+
+use crate::errors::HandleUpdateError;
+use crate::middleware::error_replies::ErrorId;
+use crate::middleware::{CommandContext, Flow, Middleware, MiddlewareResult, UpdateContext};
+
+struct CommandStarted(Instant);
+
+// Logs the failed updates and how long the commands took, along with the error ids
+// the users were given, so it has to be registered before middleware::error_replies
+pub struct Logging;
+
+impl Middleware for Logging {
+    fn after_update(&self, ctx: &mut UpdateContext, result: &MiddlewareResult) {
+        match result {
+            Ok(()) => {}
+            // The users are told what they did wrong, there's nothing to fix on our side
+            Err(
+                err @ (HandleUpdateError::Skip
+                | HandleUpdateError::MissingReply(_)
+                | HandleUpdateError::BadArgument(_)
+                | HandleUpdateError::NotFound(_)),
+            ) => debug!("{}", err),
+            // Expected for every message from the chats which aren't allowed
+            Err(err @ HandleUpdateError::NotAllowed { .. }) => info!("{}", err),
+            Err(err) => match ctx.extensions.get::<ErrorId>() {
+                Some(ErrorId(id)) => error!("Error {}: {}", id, err),
+                None => error!("Error: {}", err),
+            },
+        }
+    }
+
+    fn before_command(&self, ctx: &mut CommandContext) -> Result<Flow, HandleUpdateError> {
+        ctx.extensions.insert(CommandStarted(Instant::now()));
+
+        Ok(Flow::Continue)
+    }
+
+    fn after_command(&self, ctx: &mut CommandContext, result: &MiddlewareResult) {
+        let elapsed = ctx
+            .extensions
+            .get::<CommandStarted>()
+            .map(|CommandStarted(started)| started.elapsed());
+
+        match result {
+            Ok(()) => debug!("/{} handled in {:?}", ctx.command.name(), elapsed),
+            Err(err) => debug!("/{} failed in {:?}: {}", ctx.command.name(), elapsed, err),
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::io;
+use std::sync::Arc;
+use std::thread;
+
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use tracing::info;
+
+use crate::updates::UpdateHandler;
+
+// Spawns a thread which reloads the settings on every SIGHUP,
+// e.g. `docker kill --signal=HUP <container>`
+pub fn listen(handler: Arc<UpdateHandler>) -> io::Result<()> {
+    let mut signals = Signals::new(&[SIGHUP])?;
+
+    thread::Builder::new()
+        .name("reload".into())
+        .spawn(move || {
+            for signal in signals.forever() {
+                info!("Received signal {}, reloading the config...", signal);
+                handler.reload_settings();
+            }
+        })?;
+
+    Ok(())
+}
@@ -11,6 +11,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    chat_permissions (chat_id) {
+        chat_id -> Int8,
+        status -> Varchar,
+    }
+}
+
+diesel::table! {
+    chat_texts (chat_id, key) {
+        chat_id -> Int8,
+        key -> Varchar,
+        template -> Text,
+    }
+}
+
+diesel::table! {
+    disabled_commands (chat_id, command) {
+        chat_id -> Int8,
+        command -> Varchar,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
@@ -21,9 +43,33 @@ diesel::table! {
         first_name -> Nullable<Text>,
         last_name -> Nullable<Text>,
         username -> Nullable<Text>,
+        language -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    update_offsets (id) {
+        id -> Int4,
+        last_update_id -> Int8,
+    }
+}
+
+diesel::table! {
+    user_roles (chat_id, user_id, role) {
+        chat_id -> Int8,
+        user_id -> Int8,
+        role -> Varchar,
     }
 }
 
 diesel::joinable!(afk_events -> users (user_id));
 
-diesel::allow_tables_to_appear_in_same_query!(afk_events, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    afk_events,
+    chat_permissions,
+    chat_texts,
+    disabled_commands,
+    update_offsets,
+    user_roles,
+    users,
+);
@@ -1,3 +1,9 @@
 pub mod afk_event;
+pub mod chat_permission;
+pub mod chat_text;
+pub mod disabled_command;
+pub mod errors;
+pub mod update_offset;
 pub mod user;
+pub mod user_role;
 pub mod weather;
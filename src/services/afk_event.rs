@@ -28,12 +28,12 @@ fn get_username(from: &User) -> String {
 pub fn render_template(
     template: &Template,
     from: &User,
-    message: Option<&String>,
+    message: String,
     duration: Duration,
 ) -> String {
     let globals = liquid::to_object(&AfkEventTemplateGlobals {
         username: get_username(from),
-        message: message.cloned().unwrap_or_else(|| "N/A".into()),
+        message,
         duration: format_duration(duration).to_string(),
     })
     .expect("Failed to serialize AfkEventTemplateGlobals to liquid::Object");
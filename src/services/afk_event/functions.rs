@@ -1,14 +1,18 @@
+use crate::metrics;
 use crate::services::afk_event::errors::ServiceError;
 use crate::services::user::functions::{
     get_by_telegram_user, get_by_telegram_user_or_create, User,
 };
 
+use crate::i18n::Locale;
 use crate::services::afk_event::render_template;
+use crate::services::chat_text::ChatText;
 use crate::settings::Settings;
 use chrono::prelude::*;
 use diesel::prelude::*;
 use diesel::result::Error;
 use frankenstein::Message;
+use liquid::Template;
 use std::time::Duration;
 
 #[derive(Copy, Clone)]
@@ -40,7 +44,14 @@ pub struct AfkEvent {
 }
 
 impl AfkEvent {
-    pub fn to_string(&self, settings: &Settings, message: &Message) -> String {
+    // The chat's own template is used instead of the one from the settings if there's one
+    pub fn to_string(
+        &self,
+        settings: &Settings,
+        locale: &Locale,
+        chat_template: Option<&Template>,
+        message: &Message,
+    ) -> String {
         let event_duration = Duration::from_secs(
             (self.ended_at.unwrap() - self.started_at)
                 .to_std()
@@ -48,15 +59,17 @@ impl AfkEvent {
                 .as_secs(),
         );
 
-        let template = match EventType::from(self.event_type) {
-            EventType::Sleep => settings.wake_up_template(),
-            EventType::Work => settings.back_from_work_template(),
-        };
+        let template = chat_template.unwrap_or_else(|| match self.event_type() {
+            EventType::Sleep => settings.wake_up_template(locale),
+            EventType::Work => settings.back_from_work_template(locale),
+        });
 
         render_template(
             template,
             message.from.as_ref().unwrap(),
-            self.message.as_ref(),
+            self.message
+                .clone()
+                .unwrap_or_else(|| locale.text("afk.no_message")),
             event_duration,
         )
     }
@@ -64,6 +77,14 @@ impl AfkEvent {
     pub fn event_type(&self) -> EventType {
         EventType::from(self.event_type)
     }
+
+    // The text sent when the event ends
+    pub fn chat_text(&self) -> ChatText {
+        match self.event_type() {
+            EventType::Sleep => ChatText::WakeUpFormat,
+            EventType::Work => ChatText::BackFromWorkFormat,
+        }
+    }
 }
 
 #[derive(Insertable)]
@@ -86,10 +107,12 @@ pub enum ActionType {
 fn reset_event(conn: &mut PgConnection, event_id: i32) -> Result<AfkEvent> {
     use crate::schema::afk_events::dsl::{afk_events, ended_at, id};
 
-    diesel::update(afk_events.filter(id.eq(event_id)))
-        .set(ended_at.eq::<Option<NaiveDateTime>>(None))
-        .get_result::<AfkEvent>(conn)
-        .map_err(ServiceError::from)
+    metrics::time_db_query("reset_event", || {
+        diesel::update(afk_events.filter(id.eq(event_id)))
+            .set(ended_at.eq::<Option<NaiveDateTime>>(None))
+            .get_result::<AfkEvent>(conn)
+    })
+    .map_err(ServiceError::from)
 }
 
 pub fn begin_event(
@@ -114,10 +137,12 @@ pub fn begin_event(
 pub fn end_event(conn: &mut PgConnection, event_id: i32) -> Result<AfkEvent> {
     use crate::schema::afk_events::dsl::{afk_events, ended_at, id};
 
-    diesel::update(afk_events.filter(id.eq(event_id)))
-        .set(ended_at.eq(Some(Local::now().naive_utc())))
-        .get_result(conn)
-        .map_err(ServiceError::from)
+    metrics::time_db_query("end_event", || {
+        diesel::update(afk_events.filter(id.eq(event_id)))
+            .set(ended_at.eq(Some(Local::now().naive_utc())))
+            .get_result(conn)
+    })
+    .map_err(ServiceError::from)
 }
 
 pub fn reset_latest_event(conn: &mut PgConnection, user: &frankenstein::User) -> Result<AfkEvent> {
@@ -130,13 +155,15 @@ pub fn reset_latest_event(conn: &mut PgConnection, user: &frankenstein::User) ->
 fn get_latest_event(conn: &mut PgConnection, user: &User) -> Result<AfkEvent> {
     use crate::schema::afk_events::dsl::id;
 
-    AfkEvent::belonging_to(user)
-        .order_by(id.desc())
-        .get_result::<AfkEvent>(conn)
-        .map_err(|err| match err {
-            Error::NotFound => ServiceError::NotFound,
-            err => ServiceError::Default(err.to_string()),
-        })
+    metrics::time_db_query("get_latest_event", || {
+        AfkEvent::belonging_to(user)
+            .order_by(id.desc())
+            .get_result::<AfkEvent>(conn)
+    })
+    .map_err(|err| match err {
+        Error::NotFound => ServiceError::NotFound,
+        err => ServiceError::Default(err.to_string()),
+    })
 }
 
 pub fn get_afk_users(conn: &mut PgConnection) -> Result<Vec<(i64, i32)>> {
@@ -145,12 +172,14 @@ pub fn get_afk_users(conn: &mut PgConnection) -> Result<Vec<(i64, i32)>> {
         users::dsl::{telegram_uid, users},
     };
 
-    users
-        .inner_join(afk_events)
-        .select((telegram_uid, id))
-        .filter(ended_at.is_null())
-        .get_results(conn)
-        .map_err(ServiceError::from)
+    metrics::time_db_query("get_afk_users", || {
+        users
+            .inner_join(afk_events)
+            .select((telegram_uid, id))
+            .filter(ended_at.is_null())
+            .get_results(conn)
+    })
+    .map_err(ServiceError::from)
 }
 
 fn create_event(
@@ -163,17 +192,18 @@ fn create_event(
 
     let started_at = Local::now().naive_utc();
 
-    match diesel::insert_into(afk_events)
-        .values(InsertableAfkEvent {
-            started_at,
-            ended_at: None,
-            message: Some(message.clone()),
-            user_id,
-            event_type: event_type as i32,
-        })
-        .returning(id)
-        .get_result(conn)
-    {
+    match metrics::time_db_query("create_event", || {
+        diesel::insert_into(afk_events)
+            .values(InsertableAfkEvent {
+                started_at,
+                ended_at: None,
+                message: Some(message.clone()),
+                user_id,
+                event_type: event_type as i32,
+            })
+            .returning(id)
+            .get_result(conn)
+    }) {
         Ok(event_id) => Ok(AfkEvent {
             id: event_id,
             started_at,
@@ -0,0 +1,31 @@
+pub mod functions;
+
+// What the owners have decided about a chat with /allow_chat, /deny_chat or the buttons of
+// the join request, takes precedence over [allowed_chats]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatPermission {
+    Allowed,
+    Denied,
+    // The join request is sent to the owners, the bot stays in the chat but ignores it
+    Pending,
+}
+
+impl ChatPermission {
+    const ALL: [Self; 3] = [Self::Allowed, Self::Denied, Self::Pending];
+
+    // Stored in the database
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Self::Allowed => "allowed",
+            Self::Denied => "denied",
+            Self::Pending => "pending",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|permission| permission.key() == key)
+    }
+}
@@ -0,0 +1,55 @@
+use crate::metrics;
+use crate::services::chat_permission::ChatPermission;
+use crate::services::errors::DatabaseError;
+use diesel::prelude::*;
+
+pub type Result<T> = std::result::Result<T, DatabaseError>;
+
+// None if the owners haven't been asked about the chat
+pub fn get_permission(conn: &mut PgConnection, chat: i64) -> Result<Option<ChatPermission>> {
+    use crate::schema::chat_permissions::dsl::{chat_id, chat_permissions, status};
+
+    metrics::time_db_query("get_permission", || {
+        chat_permissions
+            .filter(chat_id.eq(chat))
+            .select(status)
+            .first::<String>(conn)
+            .optional()
+    })
+    .map(|key| key.as_deref().and_then(ChatPermission::from_key))
+    .map_err(DatabaseError::from)
+}
+
+pub fn set_permission(
+    conn: &mut PgConnection,
+    chat: i64,
+    permission: ChatPermission,
+) -> Result<()> {
+    use crate::schema::chat_permissions::dsl::{chat_id, chat_permissions, status};
+
+    metrics::time_db_query("set_permission", || {
+        diesel::insert_into(chat_permissions)
+            .values((chat_id.eq(chat), status.eq(permission.key())))
+            .on_conflict(chat_id)
+            .do_update()
+            .set(status.eq(permission.key()))
+            .execute(conn)
+    })
+    .map(|_| ())
+    .map_err(DatabaseError::from)
+}
+
+// Marks the chat as pending unless the owners have already been asked or have decided about it,
+// returns false in that case, so the join request is sent once even if the workers race
+pub fn request_permission(conn: &mut PgConnection, chat: i64) -> Result<bool> {
+    use crate::schema::chat_permissions::dsl::{chat_id, chat_permissions, status};
+
+    metrics::time_db_query("request_permission", || {
+        diesel::insert_into(chat_permissions)
+            .values((chat_id.eq(chat), status.eq(ChatPermission::Pending.key())))
+            .on_conflict_do_nothing()
+            .execute(conn)
+    })
+    .map(|inserted| inserted > 0)
+    .map_err(DatabaseError::from)
+}
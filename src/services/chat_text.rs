@@ -0,0 +1,64 @@
+pub mod functions;
+
+use liquid::Template;
+
+// The texts the chat admins can override in their chats with /settext
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChatText {
+    WakeUpFormat,
+    BackFromWorkFormat,
+    GoodNightText,
+    WeatherMessageFormat,
+}
+
+impl ChatText {
+    pub const ALL: [Self; 4] = [
+        Self::WakeUpFormat,
+        Self::BackFromWorkFormat,
+        Self::GoodNightText,
+        Self::WeatherMessageFormat,
+    ];
+
+    // Named after the settings they override
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Self::WakeUpFormat => "wake_up_format",
+            Self::BackFromWorkFormat => "back_from_work_format",
+            Self::GoodNightText => "good_night_text",
+            Self::WeatherMessageFormat => "open_weather.message_format",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|text| text.key() == key)
+    }
+
+    // The variables the template can use, with made up values to check the template with
+    pub fn sample_globals(&self) -> liquid::Object {
+        match *self {
+            Self::WakeUpFormat | Self::BackFromWorkFormat => liquid::object!({
+                "username": "durov",
+                "message": "N/A",
+                "duration": "8h 3m 12s",
+            }),
+            Self::GoodNightText => liquid::Object::new(),
+            Self::WeatherMessageFormat => liquid::object!({
+                "name": "London",
+                "temp": "+12.3",
+                "feels_like": "+10.1",
+                "description": "☁️ overcast clouds",
+            }),
+        }
+    }
+
+    // Renders the template once after parsing, so it can't fail later because of
+    // an unknown variable
+    pub fn parse(&self, template: &str) -> Result<Template, liquid::Error> {
+        let template = liquid::ParserBuilder::with_stdlib()
+            .build()?
+            .parse(template)?;
+        template.render(&self.sample_globals())?;
+
+        Ok(template)
+    }
+}
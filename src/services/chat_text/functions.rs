@@ -0,0 +1,81 @@
+use crate::cache::Cache;
+use crate::metrics;
+use crate::services::chat_text::ChatText;
+use crate::services::errors::DatabaseError;
+use diesel::prelude::*;
+use liquid::Template;
+use std::sync::Arc;
+use tracing::warn;
+
+pub type Result<T> = std::result::Result<T, DatabaseError>;
+
+pub fn get_template(conn: &mut PgConnection, chat: i64, name: &str) -> Result<Option<String>> {
+    use crate::schema::chat_texts::dsl::{chat_id, chat_texts, key, template};
+
+    metrics::time_db_query("get_template", || {
+        chat_texts
+            .filter(chat_id.eq(chat).and(key.eq(name)))
+            .select(template)
+            .first::<String>(conn)
+            .optional()
+    })
+    .map_err(DatabaseError::from)
+}
+
+pub fn set_template(conn: &mut PgConnection, chat: i64, name: &str, text: &str) -> Result<()> {
+    use crate::schema::chat_texts::dsl::{chat_id, chat_texts, key, template};
+
+    metrics::time_db_query("set_template", || {
+        diesel::insert_into(chat_texts)
+            .values((chat_id.eq(chat), key.eq(name), template.eq(text)))
+            .on_conflict((chat_id, key))
+            .do_update()
+            .set(template.eq(text))
+            .execute(conn)
+    })
+    .map(|_| ())
+    .map_err(DatabaseError::from)
+}
+
+// Returns false if the chat didn't have its own template
+pub fn reset_template(conn: &mut PgConnection, chat: i64, name: &str) -> Result<bool> {
+    use crate::schema::chat_texts::dsl::{chat_id, chat_texts, key};
+
+    metrics::time_db_query("reset_template", || {
+        diesel::delete(chat_texts.filter(chat_id.eq(chat).and(key.eq(name)))).execute(conn)
+    })
+    .map(|deleted| deleted > 0)
+    .map_err(DatabaseError::from)
+}
+
+// The template the chat admins have set for the chat, None means the default one is used.
+// The parsed templates are cached until /settext changes them
+pub fn get_chat_template(
+    conn: &mut PgConnection,
+    cache: &Cache,
+    chat: i64,
+    text: ChatText,
+) -> Result<Option<Arc<Template>>> {
+    if let Some(template) = cache.get_chat_template(chat, text) {
+        return Ok(template);
+    }
+
+    let template = get_template(conn, chat, text.key())?.and_then(|template| {
+        match text.parse(template.as_str()) {
+            Ok(template) => Some(Arc::new(template)),
+            // The templates are checked when they're set, but e.g. a liquid update could break them
+            Err(err) => {
+                warn!(
+                    "Invalid {} template in chat {}, using the default one: {}",
+                    text.key(),
+                    chat,
+                    err
+                );
+                None
+            }
+        }
+    });
+    cache.cache_chat_template(chat, text, template.clone());
+
+    Ok(template)
+}
@@ -0,0 +1,44 @@
+use crate::metrics;
+use crate::services::errors::DatabaseError;
+use diesel::prelude::*;
+
+pub type Result<T> = std::result::Result<T, DatabaseError>;
+
+pub fn get_disabled_commands(conn: &mut PgConnection, chat: i64) -> Result<Vec<String>> {
+    use crate::schema::disabled_commands::dsl::{chat_id, command, disabled_commands};
+
+    metrics::time_db_query("get_disabled_commands", || {
+        disabled_commands
+            .filter(chat_id.eq(chat))
+            .select(command)
+            .get_results::<String>(conn)
+    })
+    .map_err(DatabaseError::from)
+}
+
+pub fn set_enabled(conn: &mut PgConnection, chat: i64, name: &str, enabled: bool) -> Result<()> {
+    use crate::schema::disabled_commands::dsl::{chat_id, command, disabled_commands};
+
+    metrics::time_db_query("set_enabled", || match enabled {
+        true => diesel::delete(disabled_commands.filter(chat_id.eq(chat).and(command.eq(name))))
+            .execute(conn),
+        false => diesel::insert_into(disabled_commands)
+            .values((chat_id.eq(chat), command.eq(name)))
+            .on_conflict_do_nothing()
+            .execute(conn),
+    })
+    .map(|_| ())
+    .map_err(DatabaseError::from)
+}
+
+pub fn get_chats_with_disabled_commands(conn: &mut PgConnection) -> Result<Vec<i64>> {
+    use crate::schema::disabled_commands::dsl::{chat_id, disabled_commands};
+
+    metrics::time_db_query("get_chats_with_disabled_commands", || {
+        disabled_commands
+            .select(chat_id)
+            .distinct()
+            .get_results::<i64>(conn)
+    })
+    .map_err(DatabaseError::from)
+}
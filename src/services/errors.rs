@@ -0,0 +1,29 @@
+use crate::errors::HandleUpdateError;
+use diesel::result::Error as DieselError;
+use std::error::Error;
+use std::fmt;
+
+// The error of the services which only store things in the database and don't have any
+// errors of their own
+#[derive(Debug)]
+pub struct DatabaseError(DieselError);
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Database error: {}", self.0)
+    }
+}
+
+impl Error for DatabaseError {}
+
+impl From<DieselError> for DatabaseError {
+    fn from(pg_err: DieselError) -> Self {
+        Self(pg_err)
+    }
+}
+
+impl From<DatabaseError> for HandleUpdateError {
+    fn from(err: DatabaseError) -> Self {
+        Self::Command(err.to_string())
+    }
+}
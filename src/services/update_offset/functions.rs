@@ -0,0 +1,37 @@
+use crate::metrics;
+use crate::services::errors::DatabaseError;
+use diesel::prelude::*;
+
+// There's only one bot per database, so the offset is stored in a single row
+const OFFSET_ID: i32 = 1;
+
+pub type Result<T> = std::result::Result<T, DatabaseError>;
+
+pub fn get_last_update_id(conn: &mut PgConnection) -> Result<Option<u32>> {
+    use crate::schema::update_offsets::dsl::{last_update_id, update_offsets};
+
+    metrics::time_db_query("get_last_update_id", || {
+        update_offsets
+            .find(OFFSET_ID)
+            .select(last_update_id)
+            .get_result::<i64>(conn)
+            .optional()
+    })
+    .map(|update_id| update_id.map(|update_id| update_id as u32))
+    .map_err(DatabaseError::from)
+}
+
+pub fn save_last_update_id(conn: &mut PgConnection, update_id: u32) -> Result<()> {
+    use crate::schema::update_offsets::dsl::{id, last_update_id, update_offsets};
+
+    metrics::time_db_query("save_last_update_id", || {
+        diesel::insert_into(update_offsets)
+            .values((id.eq(OFFSET_ID), last_update_id.eq(update_id as i64)))
+            .on_conflict(id)
+            .do_update()
+            .set(last_update_id.eq(update_id as i64))
+            .execute(conn)
+    })
+    .map(|_| ())
+    .map_err(DatabaseError::from)
+}
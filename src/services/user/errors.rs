@@ -28,6 +28,9 @@ impl From<DieselError> for ServiceError {
 
 impl From<ServiceError> for HandleUpdateError {
     fn from(err: ServiceError) -> Self {
-        Self::Command(err.to_string())
+        match err {
+            ServiceError::NotFound => Self::NotFound("user".into()),
+            err => Self::Command(err.to_string()),
+        }
     }
 }
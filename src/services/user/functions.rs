@@ -1,3 +1,4 @@
+use crate::metrics;
 use crate::services::user::errors::ServiceError;
 use diesel::prelude::*;
 use diesel::result::Error;
@@ -6,13 +7,15 @@ use diesel::result::Error;
 #[table_name = "crate::schema::users"]
 pub struct User {
     pub id: i32,
-    telegram_uid: i64,
+    pub telegram_uid: i64,
     is_paying: bool,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
     first_name: Option<String>,
     last_name: Option<String>,
     username: Option<String>,
+    // Chosen with /language, None means the language of the user's Telegram app
+    pub language: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -32,10 +35,11 @@ pub type Result<T> = std::result::Result<T, ServiceError>;
 pub fn get_by_telegram_user(conn: &mut PgConnection, user: &frankenstein::User) -> Result<User> {
     use crate::schema::users::dsl::{telegram_uid as tg_uid, users};
 
-    match users
-        .filter(tg_uid.eq(user.id as i64))
-        .get_result::<User>(conn)
-    {
+    match metrics::time_db_query("get_by_telegram_user", || {
+        users
+            .filter(tg_uid.eq(user.id as i64))
+            .get_result::<User>(conn)
+    }) {
         Ok(user) => Ok(user),
         Err(err) => match err {
             Error::NotFound => Err(ServiceError::NotFound),
@@ -44,6 +48,18 @@ pub fn get_by_telegram_user(conn: &mut PgConnection, user: &frankenstein::User)
     }
 }
 
+pub fn get_by_username(conn: &mut PgConnection, name: &str) -> Result<User> {
+    use crate::schema::users::dsl::{username, users};
+
+    metrics::time_db_query("get_by_username", || {
+        users.filter(username.eq(name)).get_result::<User>(conn)
+    })
+    .map_err(|err| match err {
+        Error::NotFound => ServiceError::NotFound,
+        err => err.into(),
+    })
+}
+
 pub fn get_by_telegram_user_or_create(
     conn: &mut PgConnection,
     user: &frankenstein::User,
@@ -63,18 +79,17 @@ pub fn create(
 ) -> Result<User> {
     use crate::schema::users::dsl::users;
 
-    diesel::insert_into(users)
-        .values(InsertableUser {
-            telegram_uid: user.id as i64,
-            is_paying,
-            latitude,
-            longitude,
-            first_name: Some(user.first_name.clone()),
-            last_name: user.last_name.clone(),
-            username: user.username.clone(),
-        })
-        .get_result::<User>(conn)
-        .map_err(ServiceError::from)
+    let query = diesel::insert_into(users).values(InsertableUser {
+        telegram_uid: user.id as i64,
+        is_paying,
+        latitude,
+        longitude,
+        first_name: Some(user.first_name.clone()),
+        last_name: user.last_name.clone(),
+        username: user.username.clone(),
+    });
+
+    metrics::time_db_query("create", || query.get_result::<User>(conn)).map_err(ServiceError::from)
 }
 
 pub fn set_paying_status(
@@ -88,10 +103,31 @@ pub fn set_paying_status(
 
     let user = get_by_telegram_user_or_create(conn, user)?;
 
-    diesel::update(users.filter(telegram_uid_db.eq(user.telegram_uid)))
-        .set(is_paying_db.eq(is_paying))
-        .get_result::<User>(conn)
-        .map_err(ServiceError::from)
+    metrics::time_db_query("set_paying_status", || {
+        diesel::update(users.filter(telegram_uid_db.eq(user.telegram_uid)))
+            .set(is_paying_db.eq(is_paying))
+            .get_result::<User>(conn)
+    })
+    .map_err(ServiceError::from)
+}
+
+// Users mentioned by a username can only be found if they've used the bot before
+pub fn set_paying_status_by_username(
+    conn: &mut PgConnection,
+    username: &str,
+    is_paying: bool,
+) -> Result<User> {
+    use crate::schema::users::dsl::{is_paying as is_paying_db, username as username_db, users};
+
+    metrics::time_db_query("set_paying_status_by_username", || {
+        diesel::update(users.filter(username_db.eq(username)))
+            .set(is_paying_db.eq(is_paying))
+            .get_result::<User>(conn)
+    })
+    .map_err(|err| match err {
+        Error::NotFound => ServiceError::NotFound,
+        err => err.into(),
+    })
 }
 
 pub fn set_location(
@@ -106,8 +142,44 @@ pub fn set_location(
 
     let user = get_by_telegram_user_or_create(conn, user)?;
 
-    diesel::update(users.filter(telegram_uid_db.eq(user.telegram_uid)))
-        .set((latitude_db.eq(latitude), longitude_db.eq(longitude)))
-        .get_result::<User>(conn)
-        .map_err(ServiceError::from)
+    metrics::time_db_query("set_location", || {
+        diesel::update(users.filter(telegram_uid_db.eq(user.telegram_uid)))
+            .set((latitude_db.eq(latitude), longitude_db.eq(longitude)))
+            .get_result::<User>(conn)
+    })
+    .map_err(ServiceError::from)
+}
+
+// None if the user hasn't chosen a language or hasn't used the bot yet
+pub fn get_language(conn: &mut PgConnection, user_id: u64) -> Result<Option<String>> {
+    use crate::schema::users::dsl::{language, telegram_uid, users};
+
+    metrics::time_db_query("get_language", || {
+        users
+            .filter(telegram_uid.eq(user_id as i64))
+            .select(language)
+            .get_result::<Option<String>>(conn)
+            .optional()
+    })
+    .map(Option::flatten)
+    .map_err(ServiceError::from)
+}
+
+pub fn set_language(
+    conn: &mut PgConnection,
+    user: &frankenstein::User,
+    language: Option<&str>,
+) -> Result<User> {
+    use crate::schema::users::dsl::{
+        language as language_db, telegram_uid as telegram_uid_db, users,
+    };
+
+    let user = get_by_telegram_user_or_create(conn, user)?;
+
+    metrics::time_db_query("set_language", || {
+        diesel::update(users.filter(telegram_uid_db.eq(user.telegram_uid)))
+            .set(language_db.eq(language))
+            .get_result::<User>(conn)
+    })
+    .map_err(ServiceError::from)
 }
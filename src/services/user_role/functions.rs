@@ -0,0 +1,55 @@
+use crate::metrics;
+use crate::services::errors::DatabaseError;
+use diesel::prelude::*;
+
+pub type Result<T> = std::result::Result<T, DatabaseError>;
+
+// user is the Telegram user id, the roles are granted to the users who've never used the bot too
+pub fn has_role(conn: &mut PgConnection, chat: i64, user: u64, name: &str) -> Result<bool> {
+    use crate::schema::user_roles::dsl::{chat_id, role, user_id, user_roles};
+
+    metrics::time_db_query("has_role", || {
+        diesel::select(diesel::dsl::exists(
+            user_roles.filter(
+                chat_id
+                    .eq(chat)
+                    .and(user_id.eq(user as i64))
+                    .and(role.eq(name)),
+            ),
+        ))
+        .get_result::<bool>(conn)
+    })
+    .map_err(DatabaseError::from)
+}
+
+pub fn grant(conn: &mut PgConnection, chat: i64, user: u64, name: &str) -> Result<()> {
+    use crate::schema::user_roles::dsl::{chat_id, role, user_id, user_roles};
+
+    metrics::time_db_query("grant", || {
+        diesel::insert_into(user_roles)
+            .values((chat_id.eq(chat), user_id.eq(user as i64), role.eq(name)))
+            .on_conflict_do_nothing()
+            .execute(conn)
+    })
+    .map(|_| ())
+    .map_err(DatabaseError::from)
+}
+
+// Returns false if the user didn't have the role
+pub fn revoke(conn: &mut PgConnection, chat: i64, user: u64, name: &str) -> Result<bool> {
+    use crate::schema::user_roles::dsl::{chat_id, role, user_id, user_roles};
+
+    metrics::time_db_query("revoke", || {
+        diesel::delete(
+            user_roles.filter(
+                chat_id
+                    .eq(chat)
+                    .and(user_id.eq(user as i64))
+                    .and(role.eq(name)),
+            ),
+        )
+        .execute(conn)
+    })
+    .map(|deleted| deleted > 0)
+    .map_err(DatabaseError::from)
+}
@@ -1,8 +1,13 @@
+use crate::i18n::Locale;
+use crate::metrics;
 use crate::settings::Settings;
 use serde::{Deserialize, Serialize};
 use std::{fmt, io};
 use ureq::Error;
 
+#[cfg(test)]
+pub mod fake;
+
 #[derive(Debug, Deserialize)]
 pub struct WeatherResponseMain {
     pub feels_like: f64,
@@ -64,34 +69,67 @@ struct WeatherGlobals {
     description: String,
 }
 
-pub fn get_weather(
-    identifier: Identifier,
-    settings: &Settings,
-) -> Result<WeatherResponse, WeatherError> {
-    let mut request = ureq::get("https://api.openweathermap.org/data/2.5/weather")
-        .query("units", settings.open_weather.units.as_str())
-        .query("lang", settings.open_weather.language.as_str())
-        .query("appid", settings.open_weather.api_key.as_str());
-
-    match identifier {
-        Identifier::Location {
-            latitude,
-            longitude,
-        } => {
-            let latitude = latitude.to_string();
-            let longitude = longitude.to_string();
-            request = request
-                .query("lat", latitude.as_str())
-                .query("lon", longitude.as_str());
-        }
-        Identifier::Name(ref query) => {
-            request = request.query("q", query.as_str());
-        }
-    };
+// Where the weather comes from. The bot only asks for it through this trait, so it can run
+// against something other than OpenWeather, e.g. fake::FakeWeather
+pub trait WeatherClient: Send + Sync {
+    // The descriptions are in the language of the locale unless the config sets one
+    fn get_weather(
+        &self,
+        identifier: Identifier,
+        settings: &Settings,
+        locale: &Locale,
+    ) -> Result<WeatherResponse, WeatherError>;
+}
 
-    let result: WeatherResponse = request.call()?.into_json()?;
+pub struct OpenWeather;
+
+impl WeatherClient for OpenWeather {
+    fn get_weather(
+        &self,
+        identifier: Identifier,
+        settings: &Settings,
+        locale: &Locale,
+    ) -> Result<WeatherResponse, WeatherError> {
+        let language = settings.open_weather.language(locale);
+        let mut request = ureq::get("https://api.openweathermap.org/data/2.5/weather")
+            .query("units", settings.open_weather.units.as_str())
+            .query("lang", language.as_str())
+            .query("appid", settings.open_weather.api_key.as_str());
+
+        match identifier {
+            Identifier::Location {
+                latitude,
+                longitude,
+            } => {
+                let latitude = latitude.to_string();
+                let longitude = longitude.to_string();
+                request = request
+                    .query("lat", latitude.as_str())
+                    .query("lon", longitude.as_str());
+            }
+            Identifier::Name(ref query) => {
+                request = request.query("q", query.as_str());
+            }
+        };
+
+        let timer = metrics::OPENWEATHER_REQUEST_DURATION.start_timer();
+        let result = request
+            .call()
+            .map_err(WeatherError::from)
+            .and_then(|response| Ok(response.into_json::<WeatherResponse>()?));
+        timer.observe_duration();
+
+        if let Err(ref err) = result {
+            let kind = match err {
+                WeatherError::Request(Error::Status(404, _)) => "not_found",
+                WeatherError::Request(_) => "request",
+                WeatherError::Json(_) => "json",
+            };
+            metrics::OPENWEATHER_ERRORS.with_label_values(&[kind]).inc();
+        }
 
-    Ok(result)
+        result
+    }
 }
 
 fn get_icon<'a>(id: i64) -> Option<&'a str> {
@@ -129,7 +167,7 @@ fn get_icon<'a>(id: i64) -> Option<&'a str> {
     None
 }
 
-pub fn format_weather_data(data: &WeatherResponse, settings: &Settings) -> String {
+pub fn format_weather_data(data: &WeatherResponse, template: &liquid::Template) -> String {
     let mut description = String::new();
 
     if !data.weather.is_empty() {
@@ -155,9 +193,7 @@ pub fn format_weather_data(data: &WeatherResponse, settings: &Settings) -> Strin
     })
     .expect("Failed to serialize WeatherGlobals to liquid::Object");
 
-    settings
-        .open_weather
-        .message_format()
+    template
         .render(&globals)
         .expect("Failed to render a template")
 }
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+use ureq::{Error, Response};
+
+use crate::i18n::Locale;
+use crate::services::weather::{Identifier, WeatherClient, WeatherError, WeatherResponse};
+use crate::settings::Settings;
+
+// In-process WeatherClient which answers with the weather set for a place instead of asking
+// OpenWeather. The places without weather aren't found, like the unknown ones
+#[derive(Default)]
+pub struct FakeWeather {
+    // place -> the response of OpenWeather, the places are the lowercase names or "lat,lon"
+    places: Mutex<HashMap<String, Value>>,
+    // The places of every request in the order they were made
+    requests: Mutex<Vec<String>>,
+}
+
+impl FakeWeather {
+    // The temperatures are in the units of the config
+    pub fn set_weather(&self, place: &str, name: &str, temp: f64, description: &str) {
+        self.places.lock().unwrap().insert(
+            place.to_lowercase(),
+            json!({
+                "name": name,
+                "main": { "temp": temp, "feels_like": temp - 1.0 },
+                "weather": [{ "id": 800, "description": description }],
+            }),
+        );
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl WeatherClient for FakeWeather {
+    fn get_weather(
+        &self,
+        identifier: Identifier,
+        _settings: &Settings,
+        _locale: &Locale,
+    ) -> Result<WeatherResponse, WeatherError> {
+        let place = match identifier {
+            Identifier::Location {
+                latitude,
+                longitude,
+            } => format!("{},{}", latitude, longitude),
+            Identifier::Name(name) => name.to_lowercase(),
+        };
+        self.requests.lock().unwrap().push(place.clone());
+
+        match self.places.lock().unwrap().get(&place) {
+            Some(response) => Ok(serde_json::from_value(response.clone())
+                .expect("Failed to build the weather response")),
+            None => Err(WeatherError::Request(Error::Status(
+                404,
+                Response::new(
+                    404,
+                    "Not Found",
+                    r#"{"cod":"404","message":"city not found"}"#,
+                )
+                .expect("Failed to build the not found response"),
+            ))),
+        }
+    }
+}
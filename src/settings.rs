@@ -1,46 +1,56 @@
-use config::{Config, ConfigError};
+use config::{Config, ConfigError, Value};
 use frankenstein::Message;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::time::Duration;
 
-use crate::commands::{donate, gn, rafk, shuffle, weather, work};
 use crate::errors::HandleUpdateError;
+use crate::i18n;
+use crate::i18n::Locale;
+use crate::services::chat_permission::ChatPermission;
+use crate::services::chat_text::ChatText;
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct OpenWeatherSettings {
     pub api_key: String,
-    pub language: String,
     pub units: String,
+    language: Option<String>,
     message_format: Option<String>,
     #[serde(skip)]
     _message_format_tpl: Option<liquid::Template>,
+    inline_cache_time: Option<u64>,
 }
 
 impl Debug for OpenWeatherSettings {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "OpenWeatherSettings<api_key={}, language={}, units={}, message_format={:?} (liquid::Template initialized: {})>",
-               self.api_key, self.language, self.units, self.message_format, self._message_format_tpl.is_some())
+        write!(f, "OpenWeatherSettings<api_key={}, units={}, language={:?}, message_format={:?} (liquid::Template initialized: {}), inline_cache_time={:?}>",
+               self.api_key, self.units, self.language, self.message_format, self._message_format_tpl.is_some(), self.inline_cache_time)
     }
 }
 
 impl OpenWeatherSettings {
-    pub fn message_format(&self) -> &liquid::Template {
-        self._message_format_tpl.as_ref().unwrap()
+    // The one from the bundle of the user's language unless it's set
+    pub fn message_format<'a>(&'a self, locale: &'a Locale) -> &'a liquid::Template {
+        self._message_format_tpl
+            .as_ref()
+            .unwrap_or_else(|| locale.template("open_weather.message_format"))
     }
-}
 
-#[derive(Debug, Deserialize)]
-pub struct CommandsMap {
-    pub donate: donate::CommandSettings,
-    pub gn: gn::CommandSettings,
-    pub weather: weather::CommandSettings,
-    pub shuffle: shuffle::CommandSettings,
-    pub work: work::CommandSettings,
-    pub rafk: rafk::CommandSettings,
+    // The one from the bundle of the user's language unless it's set
+    pub fn language(&self, locale: &Locale) -> String {
+        self.language
+            .clone()
+            .unwrap_or_else(|| locale.text("open_weather.language"))
+    }
+
+    pub fn inline_cache_time(&self) -> Duration {
+        Duration::from_secs(self.inline_cache_time.unwrap_or(300))
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct AllowedChatsSettings {
     allow_unspecified: Option<bool>,
     private: Option<HashMap<i64, bool>>,
@@ -48,14 +58,238 @@ struct AllowedChatsSettings {
     supergroup: Option<HashMap<i64, bool>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
+pub struct WebhookSettings {
+    pub url: String,
+    pub listen: String,
+    pub secret_token: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PollingSettings {
+    timeout: Option<u32>,
+    max_backoff: Option<u64>,
+}
+
+impl PollingSettings {
+    pub fn timeout(&self) -> u32 {
+        self.timeout.unwrap_or(5)
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_secs(self.max_backoff.unwrap_or(60))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RateLimitSettings {
+    global_per_second: Option<usize>,
+    private_per_second: Option<usize>,
+    group_per_minute: Option<usize>,
+    max_retries: Option<u32>,
+}
+
+impl RateLimitSettings {
+    pub fn global_per_second(&self) -> usize {
+        self.global_per_second.unwrap_or(30)
+    }
+
+    pub fn private_per_second(&self) -> usize {
+        self.private_per_second.unwrap_or(1)
+    }
+
+    pub fn group_per_minute(&self) -> usize {
+        self.group_per_minute.unwrap_or(20)
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(3)
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct CooldownSettings {
+    // Drop the commands on cooldown without replying
+    #[serde(default)]
+    pub silent: bool,
+    text: Option<String>,
+    #[serde(skip)]
+    _text_tpl: Option<liquid::Template>,
+}
+
+impl Debug for CooldownSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CooldownSettings<silent={}, text={:?} (liquid::Template initialized: {})>",
+            self.silent,
+            self.text,
+            self._text_tpl.is_some()
+        )
+    }
+}
+
+impl CooldownSettings {
+    pub fn template<'a>(&'a self, locale: &'a Locale) -> &'a liquid::Template {
+        self._text_tpl
+            .as_ref()
+            .unwrap_or_else(|| locale.template("cooldown.text"))
+    }
+}
+
+// The kinds of errors explained to the user, the rest are only logged
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ErrorReply {
+    MissingReply,
+    BadArgument,
+    NotFound,
+    Upstream,
+    Unexpected,
+}
+
+impl ErrorReply {
+    const ALL: [Self; 5] = [
+        Self::MissingReply,
+        Self::BadArgument,
+        Self::NotFound,
+        Self::Upstream,
+        Self::Unexpected,
+    ];
+
+    // The same in the settings and in the bundles
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Self::MissingReply => "missing_reply",
+            Self::BadArgument => "bad_argument",
+            Self::NotFound => "not_found",
+            Self::Upstream => "upstream",
+            Self::Unexpected => "unexpected",
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct ErrorRepliesSettings {
+    missing_reply: Option<String>,
+    bad_argument: Option<String>,
+    not_found: Option<String>,
+    upstream: Option<String>,
+    unexpected: Option<String>,
+    #[serde(skip)]
+    _templates: HashMap<ErrorReply, liquid::Template>,
+}
+
+impl Debug for ErrorRepliesSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ErrorRepliesSettings<missing_reply={:?}, bad_argument={:?}, not_found={:?}, upstream={:?}, unexpected={:?} (liquid::Templates initialized: {})>",
+            self.missing_reply,
+            self.bad_argument,
+            self.not_found,
+            self.upstream,
+            self.unexpected,
+            self._templates.len()
+        )
+    }
+}
+
+impl ErrorRepliesSettings {
+    fn text(&self, reply: ErrorReply) -> Option<&String> {
+        match reply {
+            ErrorReply::MissingReply => self.missing_reply.as_ref(),
+            ErrorReply::BadArgument => self.bad_argument.as_ref(),
+            ErrorReply::NotFound => self.not_found.as_ref(),
+            ErrorReply::Upstream => self.upstream.as_ref(),
+            ErrorReply::Unexpected => self.unexpected.as_ref(),
+        }
+    }
+
+    fn parse_templates(&mut self) -> Vec<ConfigError> {
+        // Every reply is rendered with all of the variables, the ones it doesn't have are empty
+        let sample = liquid::object!({
+            "expected": "a message with a location",
+            "error": "Missing argument: user",
+            "what": "user",
+            "error_id": "0badc0de",
+        });
+
+        let mut errors = Vec::new();
+        for reply in ErrorReply::ALL.iter() {
+            let setting = format!("[error_replies].{}", reply.key());
+            match parse_template(self.text(*reply), setting.as_str(), &sample) {
+                Ok(Some(template)) => {
+                    self._templates.insert(*reply, template);
+                }
+                Ok(None) => {}
+                Err(err) => errors.push(err),
+            }
+        }
+
+        errors
+    }
+
+    pub fn template<'a>(&'a self, reply: ErrorReply, locale: &'a Locale) -> &'a liquid::Template {
+        self._templates
+            .get(&reply)
+            .unwrap_or_else(|| locale.template(format!("error_replies.{}", reply.key()).as_str()))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RolesSettings {
+    chat_admins_cache_time: Option<u64>,
+}
+
+impl RolesSettings {
+    pub fn chat_admins_cache_time(&self) -> Duration {
+        Duration::from_secs(self.chat_admins_cache_time.unwrap_or(300))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ConversationsSettings {
+    timeout: Option<u64>,
+}
+
+impl ConversationsSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(300))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsSettings {
+    pub listen: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LogSettings {
+    pub level: Option<String>,
+    #[serde(default)]
+    pub json: bool,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EditedCommands {
+    Rerun,
+    #[default]
+    Ignore,
+}
+
+// A new key has to be added to KEYS and to config.example.toml too.
+// The keys missing from the config get their defaults here, KEYS tells which ones are required
+#[derive(Default, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub token: String,
     pub postgres_dsn: String,
     pub admins: Vec<u64>,
     #[serde(skip)]
     _admins_map: Option<HashMap<u64, ()>>,
-    pub commands: CommandsMap,
+    // Every command reads its own section with command_settings()
+    commands: HashMap<String, Value>,
     pub open_weather: OpenWeatherSettings,
     wake_up_format: Option<String>,
     back_from_work_format: Option<String>,
@@ -64,6 +298,20 @@ pub struct Settings {
     #[serde(skip)]
     _back_from_work_format_tpl: Option<liquid::Template>,
     allowed_chats: AllowedChatsSettings,
+    pub webhook: Option<WebhookSettings>,
+    pub polling: PollingSettings,
+    workers: Option<usize>,
+    pub edited_commands: EditedCommands,
+    pub rate_limit: RateLimitSettings,
+    pub log: LogSettings,
+    pub metrics: Option<MetricsSettings>,
+    pub cooldown: CooldownSettings,
+    // language_code -> command name -> description
+    command_descriptions: HashMap<String, HashMap<String, String>>,
+    pub roles: RolesSettings,
+    pub error_replies: ErrorRepliesSettings,
+    pub conversations: ConversationsSettings,
+    default_language: Option<String>,
 }
 
 impl Debug for Settings {
@@ -71,7 +319,7 @@ impl Debug for Settings {
         write!(
             f,
             "<Settings token={} postgres_dsn={} admins={:?} commands={:?} open_weather={:?} \
-        wake_up_format={:?} back_from_work_format={:?} allowed_chats={:?}>",
+        wake_up_format={:?} back_from_work_format={:?} allowed_chats={:?} webhook={:?} polling={:?} workers={:?} edited_commands={:?} rate_limit={:?} log={:?} metrics={:?} cooldown={:?} command_descriptions={:?} roles={:?} error_replies={:?} conversations={:?} default_language={:?}>",
             self.token,
             self.postgres_dsn,
             self.admins,
@@ -79,84 +327,248 @@ impl Debug for Settings {
             self.open_weather,
             self.wake_up_format,
             self.back_from_work_format,
-            self.allowed_chats
+            self.allowed_chats,
+            self.webhook,
+            self.polling,
+            self.workers,
+            self.edited_commands,
+            self.rate_limit,
+            self.log,
+            self.metrics,
+            self.cooldown,
+            self.command_descriptions,
+            self.roles,
+            self.error_replies,
+            self.conversations,
+            self.default_language
         )
     }
 }
 
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
-        let mut s = Config::default();
-        s.merge(config::File::with_name("config"))?
-            .merge(config::Environment::with_prefix("bot"))?;
+        Self::parse(Self::load())
+    }
+
+    // The settings of the tests, read the same way as config.toml. The later sources override
+    // the keys of the earlier ones, like the environment overrides config.toml
+    #[cfg(test)]
+    pub fn from_toml(sources: &[&str]) -> Result<Self, ConfigError> {
+        Self::parse(Self::load_toml(sources))
+    }
 
-        let mut s: Self = s.try_into()?;
+    // Like load(), for the tests of the broken configs
+    #[cfg(test)]
+    pub fn load_toml(sources: &[&str]) -> (Self, Vec<ConfigError>) {
+        let mut config = Config::default();
+        for toml in sources {
+            if let Err(err) = config.merge(config::File::from_str(toml, config::FileFormat::Toml)) {
+                return (Self::default(), vec![err]);
+            }
+        }
 
-        s._admins_map = Some(s.admins.iter().map(|i| (i.to_owned(), ())).collect());
+        Self::from_config(&config)
+    }
 
-        if s.open_weather.message_format.is_none() {
-            s.open_weather.message_format = Some(
-                "{{ name }}: {{ temp }} (feels like {{ feels_like }}), {{ description }}".into(),
-            );
+    fn parse((mut s, mut errors): (Self, Vec<ConfigError>)) -> Result<Self, ConfigError> {
+        errors.extend(s.parse_templates());
+        if !errors.is_empty() {
+            return Err(join_errors(errors));
         }
 
-        if s.wake_up_format.is_none() {
-            s.wake_up_format = Some(
-                "{{ username }} have finished their sleep: {{ message }}. They've slept for {{ duration }}"
-                    .into()
-            );
+        Ok(s)
+    }
+
+    // Reads the config with every problem of its keys instead of the first one,
+    // the templates are parsed with parse_templates(). The broken keys get their defaults,
+    // so the rest of the config can still be checked, but the settings are only usable
+    // without errors
+    pub fn load() -> (Self, Vec<ConfigError>) {
+        match Self::read() {
+            Ok(config) => Self::from_config(&config),
+            Err(err) => (Self::default(), vec![err]),
         }
+    }
+
+    fn from_config(config: &Config) -> (Self, Vec<ConfigError>) {
+        let (valid, mut errors) = check_keys(config);
+        let mut s: Self = valid.try_into().unwrap_or_else(|err| {
+            errors.push(err);
+            Self::default()
+        });
+        s._admins_map = Some(s.admins.iter().map(|i| (i.to_owned(), ())).collect());
 
-        if s.back_from_work_format.is_none() {
-            s.back_from_work_format = Some(
-                "{{ username }} have finished working: {{ message }}. They've worked for {{ duration }}"
-                    .into(),
-            );
+        (s, errors)
+    }
+
+    fn read() -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        config
+            .merge(config::File::with_name("config"))?
+            .merge(config::Environment::with_prefix("bot"))?;
+
+        Ok(config)
+    }
+
+    // Also checks the settings serde can't, e.g. default_language
+    pub fn parse_templates(&mut self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if let Some(language) = self.default_language.as_ref() {
+            if !i18n::is_supported(language) {
+                errors.push(ConfigError::Message(format!(
+                    "There's an error in your default_language setting! There's no {} bundle, the languages are: {}",
+                    language,
+                    i18n::languages().join(", ")
+                )));
+            }
         }
 
-        s.open_weather._message_format_tpl = Some(
-            liquid::ParserBuilder::with_stdlib()
-                .build()
-                .map_err(|e| ConfigError::Message(e.to_string()))?
-                .parse(s.open_weather.message_format.as_ref().unwrap().as_str())
-                .map_err(|e| {
-                    println!("There's an error in your [open_weather].message_format setting!");
-                    ConfigError::Message(e.to_string())
-                })?,
+        let afk_sample = ChatText::WakeUpFormat.sample_globals();
+        self.open_weather._message_format_tpl = collect_error(
+            parse_template(
+                self.open_weather.message_format.as_ref(),
+                "[open_weather].message_format",
+                &ChatText::WeatherMessageFormat.sample_globals(),
+            ),
+            &mut errors,
         );
-
-        s._wake_up_format_tpl = Some(
-            liquid::ParserBuilder::with_stdlib()
-                .build()
-                .map_err(|e| ConfigError::Message(e.to_string()))?
-                .parse(s.wake_up_format.as_ref().unwrap().as_str())
-                .map_err(|e| {
-                    println!("There's an error in your wake_up_format setting!");
-                    ConfigError::Message(e.to_string())
-                })?,
+        self._wake_up_format_tpl = collect_error(
+            parse_template(self.wake_up_format.as_ref(), "wake_up_format", &afk_sample),
+            &mut errors,
         );
-
-        s._back_from_work_format_tpl = Some(
-            liquid::ParserBuilder::with_stdlib()
-                .build()
-                .map_err(|e| ConfigError::Message(e.to_string()))?
-                .parse(s.back_from_work_format.as_ref().unwrap().as_str())
-                .map_err(|e| {
-                    println!("There's an error in your back_from_work_format setting!");
-                    ConfigError::Message(e.to_string())
-                })?,
+        self._back_from_work_format_tpl = collect_error(
+            parse_template(
+                self.back_from_work_format.as_ref(),
+                "back_from_work_format",
+                &afk_sample,
+            ),
+            &mut errors,
+        );
+        self.cooldown._text_tpl = collect_error(
+            parse_template(
+                self.cooldown.text.as_ref(),
+                "[cooldown].text",
+                &liquid::object!({ "command": "weather", "remaining": "1m 2s" }),
+            ),
+            &mut errors,
         );
 
-        Ok(s)
+        errors.extend(self.error_replies.parse_templates());
+
+        errors
     }
 
     pub fn is_admin(&self, user_id: u64) -> bool {
         self._admins_map.as_ref().unwrap().contains_key(&user_id)
     }
 
+    pub fn command_description(&self, language_code: &str, command: &str) -> Option<&str> {
+        self.command_descriptions
+            .get(language_code)?
+            .get(command)
+            .map(String::as_str)
+    }
+
+    pub fn command_description_languages(&self) -> impl Iterator<Item = &str> {
+        self.command_descriptions.keys().map(String::as_str)
+    }
+
+    // The texts set in the config which the bundles have too. They override the text of every
+    // language, so /language doesn't change them. The configs written before the bundles
+    // had to set some of them, e.g. [open_weather].language
+    pub fn shadowed_texts(&self) -> Vec<String> {
+        let locale = i18n::locale(i18n::DEFAULT_LANGUAGE);
+        let mut texts: Vec<(String, String)> = vec![
+            (
+                self.open_weather.language.as_ref(),
+                "[open_weather].language",
+                "open_weather.language",
+            ),
+            (
+                self.open_weather.message_format.as_ref(),
+                "[open_weather].message_format",
+                "open_weather.message_format",
+            ),
+            (
+                self.wake_up_format.as_ref(),
+                "wake_up_format",
+                "afk.wake_up_format",
+            ),
+            (
+                self.back_from_work_format.as_ref(),
+                "back_from_work_format",
+                "afk.back_from_work_format",
+            ),
+            (
+                self.cooldown.text.as_ref(),
+                "[cooldown].text",
+                "cooldown.text",
+            ),
+        ]
+        .into_iter()
+        .filter(|(text, _, _)| text.is_some())
+        .map(|(_, setting, key)| (setting.to_string(), key.to_string()))
+        .collect();
+
+        for reply in ErrorReply::ALL.iter() {
+            if self.error_replies.text(*reply).is_some() {
+                texts.push((
+                    format!("[error_replies].{}", reply.key()),
+                    format!("error_replies.{}", reply.key()),
+                ));
+            }
+        }
+
+        // The commands read the texts of their bundle section from their config section
+        for (command, section) in self.commands.iter() {
+            let keys = match section.clone().into_table() {
+                Ok(table) => table.into_iter().map(|(key, _)| key),
+                Err(_) => continue,
+            };
+            for key in keys {
+                texts.push((
+                    format!("[commands.{}].{}", command, key),
+                    format!("{}.{}", command, key),
+                ));
+            }
+        }
+
+        let mut shadowed: Vec<String> = texts
+            .into_iter()
+            .filter(|(_, key)| locale.find(key.as_str()).is_some())
+            .map(|(setting, _)| setting)
+            .collect();
+        shadowed.sort();
+
+        shadowed
+    }
+
+    // A missing section is read as an empty one, so the commands can have optional settings only
+    pub fn command_settings<T: DeserializeOwned>(&self, command: &str) -> Result<T, ConfigError> {
+        self.commands
+            .get(command)
+            .cloned()
+            .unwrap_or_else(|| Value::new(None, HashMap::<String, Value>::new()))
+            .try_into()
+            .map_err(|e| {
+                ConfigError::Message(format!(
+                    "There's an error in your [commands.{}] settings! {}",
+                    command, e
+                ))
+            })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or(4).max(1)
+    }
+
+    // permission is the owners' decision from the database, it takes precedence over
+    // [allowed_chats]. The groups neither of them allow are pending until the owners decide
     pub fn check_for_allowed_update(
         &self,
         Message { chat, from, .. }: &Message,
+        permission: Option<ChatPermission>,
     ) -> Option<HandleUpdateError> {
         let chat_type = chat.type_field.as_str();
 
@@ -187,6 +599,7 @@ impl Settings {
                 reason: "The bot does not support channels".into(),
                 chat_name: formatted_chat_title(),
                 chat_type: chat_type.to_string(),
+                pending: false,
             });
         }
 
@@ -199,16 +612,35 @@ impl Settings {
             return None;
         }
 
+        let not_allowed = |reason: &str, pending: bool| {
+            Some(HandleUpdateError::NotAllowed {
+                chat_id,
+                reason: reason.to_string(),
+                chat_name: formatted_chat_title(),
+                chat_type: chat_type.to_string(),
+                pending,
+            })
+        };
+
+        match permission {
+            Some(ChatPermission::Allowed) => return None,
+            Some(ChatPermission::Denied) => return not_allowed("/deny_chat", false),
+            Some(ChatPermission::Pending) => return not_allowed("a pending join request", true),
+            None => {}
+        }
+
         let default = self.allowed_chats.allow_unspecified.unwrap_or(true);
 
         let chat_id_allowed_map = match chat_type {
             "private" => self.allowed_chats.private.as_ref(),
             "group" => self.allowed_chats.group.as_ref(),
             "supergroup" => self.allowed_chats.supergroup.as_ref(),
-            _ => panic!("undefined chat_type: {}", chat_type),
+            // e.g. channels, only allow_unspecified applies to them
+            _ => None,
         };
 
         let mut reason = String::new();
+        let mut pending = false;
 
         let allowed = chat_id_allowed_map
             .and_then(|map| {
@@ -224,28 +656,198 @@ impl Settings {
             })
             .or_else(|| {
                 reason = String::from("configuration: [allowed_chats].allow_unspecified is false");
+                // The owners are asked about the groups, the private chats are just ignored
+                pending = chat_type != "private";
                 Some(&default)
             })
             .unwrap()
             .to_owned();
 
         if !allowed {
-            Some(HandleUpdateError::NotAllowed {
-                chat_id,
-                reason,
-                chat_name: formatted_chat_title(),
-                chat_type: chat_type.to_string(),
-            })
+            not_allowed(reason.as_str(), pending)
         } else {
             None
         }
     }
 
-    pub fn wake_up_template(&self) -> &liquid::Template {
-        self._wake_up_format_tpl.as_ref().unwrap()
+    pub fn wake_up_template<'a>(&'a self, locale: &'a Locale) -> &'a liquid::Template {
+        self._wake_up_format_tpl
+            .as_ref()
+            .unwrap_or_else(|| locale.template("afk.wake_up_format"))
+    }
+
+    pub fn back_from_work_template<'a>(&'a self, locale: &'a Locale) -> &'a liquid::Template {
+        self._back_from_work_format_tpl
+            .as_ref()
+            .unwrap_or_else(|| locale.template("afk.back_from_work_format"))
+    }
+
+    // The language of the users whose Telegram language has no bundle
+    pub fn default_language(&self) -> &str {
+        self.default_language
+            .as_deref()
+            .unwrap_or(i18n::DEFAULT_LANGUAGE)
+    }
+}
+
+// For the texts from Settings::shadowed_texts()
+pub fn shadowed_text_warning(setting: &str) -> String {
+    format!(
+        "{} is set in the config, so it's the same in every language and /language doesn't change it. Remove it to use the language bundles",
+        setting
+    )
+}
+
+// One error per line, so all of them are reported at once
+pub fn join_errors(mut errors: Vec<ConfigError>) -> ConfigError {
+    if errors.len() == 1 {
+        return errors.remove(0);
+    }
+
+    ConfigError::Message(
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>()
+            .join("\n"),
+    )
+}
+
+fn collect_error<T: Default>(result: Result<T, ConfigError>, errors: &mut Vec<ConfigError>) -> T {
+    result.unwrap_or_else(|err| {
+        errors.push(err);
+        T::default()
+    })
+}
+
+// The missing optional keys are fine, Settings has the defaults for them
+fn check_key<T: DeserializeOwned>(
+    config: &Config,
+    key: &str,
+    required: bool,
+) -> Option<ConfigError> {
+    match config.get::<T>(key) {
+        Ok(_) => None,
+        Err(ConfigError::NotFound(_)) if !required => None,
+        Err(err) => Some(err),
     }
+}
+
+type CheckKey = fn(&Config, &str, bool) -> Option<ConfigError>;
+
+// Every key of Settings: (key, how it's read, whether it's required).
+// The [commands.<name>] sections are checked by the commands
+const KEYS: &[(&str, CheckKey, bool)] = &[
+    ("token", check_key::<String>, true),
+    ("postgres_dsn", check_key::<String>, true),
+    ("admins", check_key::<Vec<u64>>, true),
+    ("commands", check_key::<HashMap<String, Value>>, false),
+    ("open_weather", check_key::<OpenWeatherSettings>, true),
+    ("wake_up_format", check_key::<String>, false),
+    ("back_from_work_format", check_key::<String>, false),
+    ("allowed_chats", check_key::<AllowedChatsSettings>, true),
+    ("webhook", check_key::<WebhookSettings>, false),
+    ("polling", check_key::<PollingSettings>, false),
+    ("workers", check_key::<usize>, false),
+    ("edited_commands", check_key::<EditedCommands>, false),
+    ("rate_limit", check_key::<RateLimitSettings>, false),
+    ("log", check_key::<LogSettings>, false),
+    ("metrics", check_key::<MetricsSettings>, false),
+    ("cooldown", check_key::<CooldownSettings>, false),
+    (
+        "command_descriptions",
+        check_key::<HashMap<String, HashMap<String, String>>>,
+        false,
+    ),
+    ("roles", check_key::<RolesSettings>, false),
+    ("error_replies", check_key::<ErrorRepliesSettings>, false),
+    ("conversations", check_key::<ConversationsSettings>, false),
+    ("default_language", check_key::<String>, false),
+];
+
+// Reads every key of Settings on its own, so all of the broken ones are reported
+// instead of the first one. Returns the config without the broken keys
+fn check_keys(config: &Config) -> (Config, Vec<ConfigError>) {
+    let mut valid = Config::default();
+    let mut errors = Vec::new();
+
+    for (key, check, required) in KEYS {
+        if let Some(err) = check(config, key, *required) {
+            errors.push(err);
+            continue;
+        }
+        if let Ok(value) = config.get::<Value>(key) {
+            if let Err(err) = valid.set(key, value) {
+                errors.push(err);
+            }
+        }
+    }
+
+    (valid, errors)
+}
+
+// The texts are optional, the unset ones are taken from the bundles. The template is rendered
+// with the sample variables once, so it can't fail later because of an unknown variable
+fn parse_template(
+    text: Option<&String>,
+    setting: &str,
+    sample: &liquid::Object,
+) -> Result<Option<liquid::Template>, ConfigError> {
+    let text = match text {
+        Some(text) => text,
+        None => return Ok(None),
+    };
+
+    let invalid = |e: liquid::Error| {
+        ConfigError::Message(format!(
+            "There's an error in your {} setting! {}",
+            setting, e
+        ))
+    };
+    let template = liquid::ParserBuilder::with_stdlib()
+        .build()
+        .map_err(|e| ConfigError::Message(e.to_string()))?
+        .parse(text.as_str())
+        .map_err(invalid)?;
+    template.render(sample).map_err(invalid)?;
+
+    Ok(Some(template))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing;
+
+    #[test]
+    fn finds_texts_shadowing_bundles() {
+        let settings = testing::settings(
+            r#"
+            [open_weather]
+            language = "en"
+
+            [error_replies]
+            not_found = "Nope"
 
-    pub fn back_from_work_template(&self) -> &liquid::Template {
-        self._back_from_work_format_tpl.as_ref().unwrap()
+            [commands.gn]
+            good_night_text = "Good night!"
+
+            [commands.weather]
+            cooldown_per_user = 10
+
+            [commands.donate]
+            text = "https://patreon.com/defman"
+            "#,
+        );
+
+        assert_eq!(
+            settings.shadowed_texts(),
+            vec![
+                "[commands.gn].good_night_text",
+                "[error_replies].not_found",
+                "[open_weather].language",
+                // The tests set it to get rid of the duration
+                "wake_up_format",
+            ]
+        );
     }
 }
@@ -0,0 +1,100 @@
+use std::io;
+use std::process::exit;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use tracing::{info, warn};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+struct State {
+    requested: bool,
+    callbacks: Vec<Callback>,
+}
+
+#[derive(Clone)]
+pub struct Shutdown {
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+impl Shutdown {
+    // Spawns a thread which requests the shutdown on the first SIGINT/SIGTERM, and exits
+    // right away on the second one in case the bot got stuck
+    pub fn listen() -> io::Result<Self> {
+        let shutdown = Self {
+            state: Arc::new((
+                Mutex::new(State {
+                    requested: false,
+                    callbacks: Vec::new(),
+                }),
+                Condvar::new(),
+            )),
+        };
+
+        let mut signals = Signals::new(&[SIGINT, SIGTERM])?;
+        let listener = shutdown.clone();
+
+        thread::Builder::new()
+            .name("signals".into())
+            .spawn(move || {
+                for (count, signal) in signals.forever().enumerate() {
+                    if count > 0 {
+                        warn!("Received signal {} again, exiting immediately", signal);
+                        exit(1);
+                    }
+
+                    info!("Received signal {}, shutting down...", signal);
+                    listener.request();
+                }
+            })?;
+
+        Ok(shutdown)
+    }
+
+    pub fn request(&self) {
+        let (lock, condvar) = &*self.state;
+        let callbacks = {
+            let mut state = lock.lock().unwrap();
+            state.requested = true;
+            condvar.notify_all();
+            std::mem::take(&mut state.callbacks)
+        };
+
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.state.0.lock().unwrap().requested
+    }
+
+    // Registers a callback for the things that block and have to be woken up on shutdown,
+    // e.g. a server waiting for connections. Called right away if the shutdown is already requested.
+    pub fn on_request(&self, callback: impl FnOnce() + Send + 'static) {
+        {
+            let mut state = self.state.0.lock().unwrap();
+            if !state.requested {
+                state.callbacks.push(Box::new(callback));
+                return;
+            }
+        }
+
+        callback();
+    }
+
+    // Sleeps for the given duration unless the shutdown is requested in the meantime.
+    // Returns true if the shutdown was requested.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let (lock, condvar) = &*self.state;
+        let state = lock.lock().unwrap();
+        let (state, _) = condvar
+            .wait_timeout_while(state, duration, |state| !state.requested)
+            .unwrap();
+
+        state.requested
+    }
+}
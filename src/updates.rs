@@ -1,33 +1,59 @@
+use std::collections::hash_map::DefaultHasher;
 use std::error::Error;
+use std::hash::{Hash, Hasher};
 use std::process::exit;
+use std::sync::{Arc, RwLock};
 
+use config::ConfigError;
 use diesel::pg::Pg;
-use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use diesel::PgConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use frankenstein::{
-    Api, ChatId, Location, Message, MessageEntity, SendMessageParams, SetMyCommandsParams,
-    TelegramApi, Update,
+    AnswerInlineQueryParams, CallbackQuery, ChatId, InlineQuery, InlineQueryResult,
+    InlineQueryResultArticle, InputMessageContent, InputTextMessageContent, LeaveChatParams,
+    Location, Message, MessageEntity, SendMessageParams, Update, User,
 };
+use tracing::{error, field, info, info_span, warn};
+use ureq::Error as RequestError;
 
-use crate::cache::Cache;
+use crate::cache::{Cache, WeatherCard};
+use crate::client::Client;
 use crate::commands::CommandsExecutor;
 use crate::errors::HandleUpdateError;
 use crate::helpers;
-use crate::services::afk_event::functions::end_event;
-use crate::services::afk_event::{errors::ServiceError, functions::get_afk_users};
-use crate::services::weather::{format_weather_data, get_weather, Identifier};
-use crate::settings::Settings;
+use crate::i18n;
+use crate::i18n::Locale;
+use crate::metrics;
+use crate::middleware::{Extensions, Middlewares, UpdateContext};
+use crate::services::afk_event::functions::get_afk_users;
+use crate::services::chat_text::functions::get_chat_template;
+use crate::services::chat_text::ChatText;
+use crate::services::disabled_command::functions::get_chats_with_disabled_commands;
+use crate::services::update_offset::functions::{get_last_update_id, save_last_update_id};
+use crate::services::user::functions::get_language;
+use crate::services::weather::{format_weather_data, Identifier, WeatherClient, WeatherError};
+use crate::settings::{join_errors, EditedCommands, Settings};
 
 const BOT_COMMAND: &str = "bot_command";
 
-pub struct UpdateHandler<'a> {
-    pub api: &'a Api,
-    settings: &'a Settings,
-    pub commands_executor: CommandsExecutor<'a>,
+pub type PgPool = Pool<ConnectionManager<PgConnection>>;
+pub type PgPooledConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
+// Registers the commands on an executor, called again with the new settings on reload
+pub type RegisterCommands = fn(&mut CommandsExecutor);
+
+pub struct UpdateHandler {
+    pub api: Arc<dyn Client>,
+    weather: Arc<dyn WeatherClient>,
+    // Holds the settings too, both are swapped on reload and
+    // the updates in progress finish with the ones they've started with
+    commands_executor: RwLock<Arc<CommandsExecutor>>,
+    register_commands: RegisterCommands,
+    pub middlewares: Middlewares,
     bot_prefix: String,
-    postgres: PgConnection,
-    cache: &'a Cache,
+    postgres: PgPool,
+    cache: Arc<Cache>,
 }
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
@@ -40,29 +66,53 @@ fn run_migrations(
     Ok(())
 }
 
-impl<'a> UpdateHandler<'a> {
-    pub fn new(api: &'a Api, settings: &'a Settings, cache: &'a Cache) -> UpdateHandler<'a> {
+impl UpdateHandler {
+    pub fn new(
+        api: Arc<dyn Client>,
+        weather: Arc<dyn WeatherClient>,
+        settings: Arc<Settings>,
+        cache: Arc<Cache>,
+        register_commands: RegisterCommands,
+    ) -> UpdateHandler {
+        let postgres = Pool::builder()
+            // Every worker holds at most one connection, the main thread needs one to save
+            // the update offset and the reload thread one to publish the commands
+            .max_size(settings.workers() as u32 + 2)
+            .build(ConnectionManager::<PgConnection>::new(
+                settings.postgres_dsn.as_str(),
+            ))
+            .expect("Failed to connect to postgres");
+
+        let commands_executor =
+            Self::build_commands_executor(settings, &api, &weather, &cache, register_commands)
+                .unwrap_or_else(|err| {
+                    error!("{}", err);
+                    exit(1);
+                });
+
         let mut handler = UpdateHandler {
             api,
-            settings,
-            commands_executor: CommandsExecutor::new(settings, api, cache),
+            weather,
+            commands_executor: RwLock::new(Arc::new(commands_executor)),
+            register_commands,
+            middlewares: Middlewares::default(),
             bot_prefix: String::new(),
-            postgres: PgConnection::establish(settings.postgres_dsn.as_str())
-                .expect("Failed to connect to postgres"),
+            postgres,
             cache,
         };
 
-        if let Err(err) = run_migrations(&mut handler.postgres) {
-            println!("Failed to run the migrations: {}", err);
+        let mut conn = handler
+            .connection()
+            .expect("Failed to get a postgres connection");
+
+        if let Err(err) = run_migrations(&mut *conn) {
+            error!("Failed to run the migrations: {}", err);
             exit(1);
         };
 
-        match api.get_me() {
-            Ok(response) => {
-                let prefix = response
-                    .result
-                    .username
-                    .expect("Failed to get the username");
+        match handler.api.get_me() {
+            Ok(me) => {
+                let prefix = me.username.expect("Failed to get the username");
                 handler.set_bot_prefix(prefix);
             }
             Err(error) => {
@@ -70,7 +120,7 @@ impl<'a> UpdateHandler<'a> {
             }
         };
 
-        match get_afk_users(&mut handler.postgres) {
+        match get_afk_users(&mut conn) {
             Ok(ref afks) => handler.cache.populate_afk_cache(&afks),
             Err(err) => panic!("Failed to populate afk_event cache from DB: {:?}", err),
         }
@@ -78,25 +128,145 @@ impl<'a> UpdateHandler<'a> {
         handler
     }
 
+    fn build_commands_executor(
+        settings: Arc<Settings>,
+        api: &Arc<dyn Client>,
+        weather: &Arc<dyn WeatherClient>,
+        cache: &Arc<Cache>,
+        register_commands: RegisterCommands,
+    ) -> Result<CommandsExecutor, ConfigError> {
+        let mut commands_executor = CommandsExecutor::new(
+            settings,
+            Arc::clone(api),
+            Arc::clone(weather),
+            Arc::clone(cache),
+        );
+        register_commands(&mut commands_executor);
+
+        let errors = commands_executor.take_init_errors();
+        if !errors.is_empty() {
+            return Err(join_errors(errors));
+        }
+
+        Ok(commands_executor)
+    }
+
+    pub fn commands_executor(&self) -> Arc<CommandsExecutor> {
+        Arc::clone(&self.commands_executor.read().unwrap())
+    }
+
+    // Reads the config again and rebuilds the commands with it. The old settings are kept if
+    // the new ones are invalid, and the admins are told what's wrong.
+    // The settings read once on start, e.g. the token, the database and the workers, aren't
+    // changed until the restart.
+    pub fn reload_settings(&self) {
+        let result = Settings::new().and_then(|settings| {
+            Self::build_commands_executor(
+                Arc::new(settings),
+                &self.api,
+                &self.weather,
+                &self.cache,
+                self.register_commands,
+            )
+        });
+
+        match result {
+            Ok(commands_executor) => {
+                *self.commands_executor.write().unwrap() = Arc::new(commands_executor);
+                info!("Reloaded the config");
+
+                // The descriptions might have changed
+                if let Err(err) = self.send_my_commands() {
+                    error!("Failed to set my commands: {}", err);
+                }
+            }
+            Err(err) => {
+                error!("Failed to reload the config, keeping the old one: {}", err);
+                let commands_executor = self.commands_executor();
+                let locale = i18n::locale(commands_executor.settings().default_language());
+                self.notify_admins(locale.render(
+                    "reload.failed",
+                    &liquid::object!({ "error": err.to_string() }),
+                ));
+            }
+        }
+    }
+
+    fn notify_admins(&self, text: String) {
+        // Private chat ids are the user ids
+        for admin in self.commands_executor().settings().admins.iter() {
+            if let Err(err) =
+                helpers::send_text_message(self.api.as_ref(), *admin as i64, text.clone(), None)
+            {
+                warn!("Failed to notify admin {}: {}", admin, err);
+            }
+        }
+    }
+
     pub fn set_bot_prefix(&mut self, prefix: String) {
         self.bot_prefix = prefix;
     }
 
-    pub fn send_my_commands(&self) {
-        let set_my_commands_params =
-            SetMyCommandsParams::new(self.commands_executor.bot_commands());
-        self.api
-            .set_my_commands(&set_my_commands_params)
-            .expect("Failed to set my commands");
+    fn connection(&self) -> Result<PgPooledConnection, HandleUpdateError> {
+        self.postgres
+            .get()
+            .map_err(|err| HandleUpdateError::Command(err.to_string()))
+    }
+
+    pub fn last_update_id(&self) -> Option<u32> {
+        let mut conn = self.connection().ok()?;
+
+        get_last_update_id(&mut conn).unwrap_or_else(|err| {
+            error!("Failed to get the last update id: {}", err);
+            None
+        })
+    }
+
+    pub fn save_update_id(&self, update_id: u32) {
+        let result = self
+            .connection()
+            .and_then(|mut conn| save_last_update_id(&mut conn, update_id).map_err(Into::into));
+
+        if let Err(err) = result {
+            error!("Failed to save the last update id {}: {}", update_id, err);
+        }
+    }
+
+    pub fn allowed_updates(&self) -> Vec<String> {
+        vec![
+            "message".to_string(),
+            "edited_message".to_string(),
+            "callback_query".to_string(),
+            "inline_query".to_string(),
+        ]
+    }
+
+    pub fn send_my_commands(&self) -> Result<(), HandleUpdateError> {
+        let commands_executor = self.commands_executor();
+        commands_executor.send_my_commands()?;
+
+        // The chat lists have to be published again, otherwise they miss the new commands
+        let mut conn = self.connection()?;
+        let chats = get_chats_with_disabled_commands(&mut conn)?;
+        for chat_id in chats {
+            if let Err(err) = commands_executor.send_chat_commands(&mut conn, chat_id) {
+                warn!("Failed to set the commands for chat {}: {}", chat_id, err);
+            }
+        }
+
+        Ok(())
     }
 
     fn handle_command(
-        &mut self,
-        update: &Update,
+        &self,
+        ctx: &mut UpdateContext,
         message: &Message,
         command_entity: &MessageEntity,
     ) -> Result<(), HandleUpdateError> {
-        let text = message.text.as_ref().unwrap();
+        let text = match message.text.as_ref() {
+            Some(text) => text,
+            None => return Ok(()),
+        };
         let offset = command_entity.offset as usize;
 
         if offset != 0 {
@@ -106,10 +276,11 @@ impl<'a> UpdateHandler<'a> {
         let length = command_entity.length as usize;
         let command = &text[offset..offset + length];
 
-        match self.commands_executor.execute(
+        let commands_executor = ctx.commands_executor;
+        match commands_executor.execute(
+            &self.middlewares,
             self.bot_prefix.as_str(),
-            &mut self.postgres,
-            &update,
+            ctx,
             command,
             &message,
             &text[length..],
@@ -121,7 +292,7 @@ impl<'a> UpdateHandler<'a> {
 
     fn handle_location(
         &self,
-        _update: &Update,
+        ctx: &mut UpdateContext,
         message: &Message,
         Location {
             latitude,
@@ -129,24 +300,33 @@ impl<'a> UpdateHandler<'a> {
             ..
         }: &Location,
     ) -> Result<(), HandleUpdateError> {
-        let weather_data = get_weather(
+        let weather_data = self.weather.get_weather(
             Identifier::Location {
                 latitude: *latitude,
                 longitude: *longitude,
             },
-            self.settings,
+            ctx.settings,
+            ctx.locale,
+        )?;
+        let chat_template = get_chat_template(
+            ctx.conn,
+            ctx.cache,
+            message.chat.id,
+            ChatText::WeatherMessageFormat,
         )?;
+        let template = chat_template
+            .as_deref()
+            .unwrap_or_else(|| ctx.settings.open_weather.message_format(ctx.locale));
 
         let mut message_params = SendMessageParams::new(
             ChatId::Integer(message.chat.id),
-            format_weather_data(&weather_data, &self.settings),
+            format_weather_data(&weather_data, template),
         );
         message_params.set_reply_to_message_id(Some(message.message_id));
 
         self.api
             .send_message(&message_params)
             .map_err(HandleUpdateError::Api)
-            .map(|_| ())
     }
 
     fn find_command_entity(message: &Message) -> Option<&MessageEntity> {
@@ -157,33 +337,146 @@ impl<'a> UpdateHandler<'a> {
             .find(|entity| entity.type_field.as_str() == BOT_COMMAND)
     }
 
-    pub fn handle_update(&mut self, update: &Update) -> Result<(), HandleUpdateError> {
-        let message = update.message.as_ref().ok_or(HandleUpdateError::Skip)?;
+    // Handles the update and takes care of the errors, so it can be called from the worker threads
+    pub fn process_update(&self, update: &Update) {
+        let span = info_span!(
+            "update",
+            update_id = update.update_id,
+            chat_id = field::Empty,
+            user_id = field::Empty,
+            command = field::Empty,
+        );
+        if let Some(chat_id) = helpers::get_chat_id_by_update(update) {
+            span.record("chat_id", &chat_id);
+        }
+        if let Some(user) = helpers::get_user_by_update(update) {
+            span.record("user_id", &user.id);
+        }
+        let _enter = span.enter();
 
-        if let Some(err) = self.settings.check_for_allowed_update(message) {
-            return Err(err);
+        metrics::UPDATES
+            .with_label_values(&[helpers::get_update_type(update)])
+            .inc();
+
+        // The errors are logged by the logging middleware. The bot stays in the pending chats
+        // until the owners decide about them
+        if let Err(HandleUpdateError::NotAllowed {
+            chat_id,
+            chat_type,
+            pending: false,
+            ..
+        }) = self.handle_update(update)
+        {
+            if chat_type.as_str() != "private" {
+                self.leave_chat(chat_id);
+            }
         }
+    }
 
-        let user_id = helpers::get_user_id_by_message(message)?;
-        if let Some(event_id) = self.cache.get_afk_event_id(user_id) {
-            match end_event(&mut self.postgres, event_id) {
-                Ok(event) => {
-                    let _ = helpers::send_text_message(
-                        self.api,
-                        message.chat.id,
-                        event.to_string(self.settings, message),
-                        Some(message.message_id),
-                    );
+    fn leave_chat(&self, chat_id: i64) {
+        if let Err(err) = self
+            .api
+            .leave_chat(&LeaveChatParams::new(ChatId::Integer(chat_id)))
+            .map(|_| info!("Left: {}", chat_id))
+            .map_err(HandleUpdateError::from)
+        {
+            error!("Failed to leave the chat: {}", err);
+        }
+    }
+
+    pub fn handle_update(&self, update: &Update) -> Result<(), HandleUpdateError> {
+        let mut conn = self.connection().map_err(|err| {
+            error!("Failed to get a database connection: {}", err);
+            err
+        })?;
+        let commands_executor = self.commands_executor();
+        let locale = self.user_locale(
+            &mut conn,
+            commands_executor.settings(),
+            helpers::get_user_by_update(update),
+        );
+        let mut ctx = UpdateContext {
+            api: self.api.as_ref(),
+            conn: &mut conn,
+            cache: &self.cache,
+            settings: commands_executor.settings(),
+            commands_executor: &commands_executor,
+            update,
+            locale,
+            extensions: Extensions::default(),
+        };
+
+        self.middlewares
+            .run_update(&mut ctx, |ctx| self.dispatch_update(ctx))
+    }
+
+    // The language the user has chosen is cached, /language updates the cache
+    fn user_locale(
+        &self,
+        conn: &mut PgConnection,
+        settings: &Settings,
+        user: Option<&User>,
+    ) -> &'static Locale {
+        let chosen = user.and_then(|user| match self.cache.get_language(user.id) {
+            Some(language) => language,
+            None => match get_language(conn, user.id) {
+                Ok(language) => {
+                    self.cache.cache_language(user.id, language.clone());
+                    language
                 }
-                Err(ServiceError::NotFound) => {}
-                Err(err) => println!("Failed to end afk event for user {}: {}", user_id, err),
-            };
-            self.cache.cache_afk_event_id(user_id, false, event_id);
+                Err(err) => {
+                    warn!("Failed to get the language of user {}: {}", user.id, err);
+                    None
+                }
+            },
+        });
+
+        i18n::resolve(chosen.as_deref(), user, settings.default_language())
+    }
+
+    fn dispatch_update(&self, ctx: &mut UpdateContext) -> Result<(), HandleUpdateError> {
+        let update = ctx.update;
+
+        if let Some(message) = update.message.as_ref() {
+            return self.handle_message(ctx, message);
+        }
+
+        if let Some(message) = update.edited_message.as_ref() {
+            return self.handle_edited_message(ctx, message);
+        }
+
+        if let Some(query) = update.callback_query.as_ref() {
+            return self.handle_callback_query(ctx, query);
+        }
+
+        if let Some(query) = update.inline_query.as_ref() {
+            return self.handle_inline_query(ctx.settings, ctx.locale, query);
+        }
+
+        Err(HandleUpdateError::Skip)
+    }
+
+    fn handle_message(
+        &self,
+        ctx: &mut UpdateContext,
+        message: &Message,
+    ) -> Result<(), HandleUpdateError> {
+        // A command waiting for an answer gets the message instead, but the commands,
+        // e.g. /cancel, are handled as usual
+        if Self::find_command_entity(message).is_none()
+            && ctx.commands_executor.answer(
+                ctx.conn,
+                self.bot_prefix.as_str(),
+                message,
+                ctx.locale,
+            )?
+        {
+            return Ok(());
         }
 
         if let Some(err) = Self::find_command_entity(message).and_then(|entity| {
             // If there's a MessageEntity, there's some text which we can unwrap without panic
-            self.handle_command(update, message, entity).err()
+            self.handle_command(ctx, message, entity).err()
         }) {
             match err {
                 HandleUpdateError::Skip => {}
@@ -194,11 +487,504 @@ impl<'a> UpdateHandler<'a> {
         if let Some(err) = message
             .location
             .as_ref()
-            .and_then(|loc| self.handle_location(update, message, loc).err())
+            .and_then(|loc| self.handle_location(ctx, message, loc).err())
         {
             return Err(err);
         };
 
         Ok(())
     }
+
+    // Edits don't trigger the weather for locations, otherwise every live location update
+    // would be answered with a forecast
+    fn handle_edited_message(
+        &self,
+        ctx: &mut UpdateContext,
+        message: &Message,
+    ) -> Result<(), HandleUpdateError> {
+        if ctx.settings.edited_commands == EditedCommands::Ignore {
+            return Err(HandleUpdateError::Skip);
+        }
+
+        let entity = Self::find_command_entity(message).ok_or(HandleUpdateError::Skip)?;
+
+        match self.handle_command(ctx, message, entity) {
+            Err(HandleUpdateError::Skip) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn handle_callback_query(
+        &self,
+        ctx: &mut UpdateContext,
+        query: &CallbackQuery,
+    ) -> Result<(), HandleUpdateError> {
+        match ctx
+            .commands_executor
+            .execute_callback(ctx.conn, query, ctx.locale)
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_inline_query(
+        &self,
+        settings: &Settings,
+        locale: &Locale,
+        query: &InlineQuery,
+    ) -> Result<(), HandleUpdateError> {
+        let location = query.query.trim();
+        if location.is_empty() {
+            return Err(HandleUpdateError::Skip);
+        }
+
+        let cache_time = settings.open_weather.inline_cache_time();
+        // The same place is described differently in every language
+        let cache_key = format!("{}:{}", locale.language(), location);
+
+        let card = match self
+            .cache
+            .get_inline_weather(cache_key.as_str(), cache_time)
+        {
+            Some(card) => Some(card),
+            None => match self.weather.get_weather(
+                Identifier::Name(location.to_string()),
+                settings,
+                locale,
+            ) {
+                Ok(ref data) => {
+                    let card = WeatherCard {
+                        title: data.name.clone(),
+                        text: format_weather_data(
+                            data,
+                            settings.open_weather.message_format(locale),
+                        ),
+                    };
+                    self.cache
+                        .cache_inline_weather(cache_key.as_str(), card.clone(), cache_time);
+                    Some(card)
+                }
+                Err(WeatherError::Request(RequestError::Status(404, _))) => None,
+                Err(err) => return Err(err.into()),
+            },
+        };
+
+        // Telegram caches the results by their ids, so every location needs its own one
+        let mut hasher = DefaultHasher::new();
+        cache_key.to_lowercase().hash(&mut hasher);
+        let id = format!("weather-{:x}", hasher.finish());
+
+        let results = card
+            .into_iter()
+            .map(|card| {
+                let mut article = InlineQueryResultArticle::new(
+                    "article".into(),
+                    id.clone(),
+                    card.title,
+                    InputMessageContent::InputTextMessageContent(InputTextMessageContent::new(
+                        card.text.clone(),
+                    )),
+                );
+                article.set_description(Some(card.text));
+                InlineQueryResult::InlineQueryResultArticle(article)
+            })
+            .collect();
+
+        let mut answer_params = AnswerInlineQueryParams::new(query.id.clone(), results);
+        answer_params.set_cache_time(Some(cache_time.as_secs() as u32));
+
+        self.api
+            .answer_inline_query(&answer_params)
+            .map_err(HandleUpdateError::Api)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::testing::{
+        bot_user, callback_query, group, location_message, message, private, random_id, update,
+        user, TestBot,
+    };
+
+    // One of the admins of the test config
+    const OWNER_ID: i64 = 2;
+
+    #[test]
+    #[ignore]
+    fn gn_replies_and_wakes_up_on_next_message() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update(
+            "message",
+            message(10, &chat, &alice, "/gn see you tomorrow"),
+        ));
+        bot.send(update("message", message(11, &chat, &alice, "morning")));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, "Good night!".to_string(), Some(10)),
+                (
+                    chat_id,
+                    "alice is awake: see you tomorrow".to_string(),
+                    Some(11)
+                ),
+            ]
+        );
+        assert_eq!(
+            bot.client.chat_actions(),
+            vec![(chat_id, "typing".to_string())]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn rafk_without_afk_event() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+
+        bot.send(update(
+            "message",
+            message(20, &chat, &user(random_id(), "bob"), "/rafk"),
+        ));
+
+        assert_eq!(
+            bot.replies(),
+            vec![(
+                chat["id"].as_i64().unwrap(),
+                "You haven't been afk tho...".to_string(),
+                Some(20)
+            )]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn rafk_resumes_afk_event() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let carol = user(random_id(), "carol");
+
+        bot.send(update("message", message(30, &chat, &carol, "/gn")));
+        bot.send(update("message", message(31, &chat, &carol, "not yet")));
+        bot.send(update("message", message(32, &chat, &carol, "/rafk")));
+        bot.send(update("message", message(33, &chat, &carol, "now I'm up")));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, "Good night!".to_string(), Some(30)),
+                (chat_id, "carol is awake: N/A".to_string(), Some(31)),
+                (chat_id, "Good night!".to_string(), Some(32)),
+                (chat_id, "carol is awake: N/A".to_string(), Some(33)),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn weather_without_location() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+
+        bot.send(update(
+            "message",
+            message(40, &chat, &user(random_id(), "dave"), "/weather"),
+        ));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![(
+                chat_id,
+                "You don't have a location set. Send me a geolocation message and call /set_my_location on it."
+                    .to_string(),
+                Some(40)
+            )]
+        );
+        assert_eq!(
+            bot.client.chat_actions(),
+            vec![(chat_id, "find_location".to_string())]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn weather_for_replied_user_without_location() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let mut weather = message(50, &chat, &user(random_id(), "erin"), "/weather");
+        weather["reply_to_message"] = message(49, &chat, &user(random_id(), "frank"), "hi");
+
+        bot.send(update("message", weather));
+
+        assert_eq!(
+            bot.replies(),
+            vec![(
+                chat["id"].as_i64().unwrap(),
+                "This user does not have a location set.".to_string(),
+                Some(50)
+            )]
+        );
+    }
+
+    // The join request the owner got and the button they press on it
+    fn press_join_request_button(bot: &TestBot, owner: &Value, button: usize) {
+        let request = bot
+            .client
+            .calls_to("sendMessage")
+            .into_iter()
+            .find(|params| params["chat_id"] == OWNER_ID)
+            .expect("No join request sent");
+        let mut request_message = message(
+            100,
+            &private(owner),
+            &bot_user(),
+            request["text"].as_str().unwrap(),
+        );
+        request_message["reply_markup"] = request["reply_markup"].clone();
+        let data = request["reply_markup"]["inline_keyboard"][0][button]["callback_data"]
+            .as_str()
+            .unwrap();
+
+        bot.send(update(
+            "callback_query",
+            callback_query(owner, request_message, data),
+        ));
+    }
+
+    #[test]
+    #[ignore]
+    fn approved_join_request_allows_chat() {
+        let bot = TestBot::new("[allowed_chats]\nallow_unspecified = false");
+        let chat = group(random_id());
+        let chat_id = chat["id"].as_i64().unwrap();
+        let alice = user(random_id(), "alice");
+        let owner = user(OWNER_ID, "owner");
+
+        bot.send(update("message", message(1, &chat, &alice, "/gn")));
+        // The request is only sent once
+        bot.send(update("message", message(2, &chat, &alice, "/gn")));
+
+        let request = bot.client.calls_to("sendMessage");
+        assert_eq!(request.len(), 1);
+        assert_eq!(request[0]["chat_id"], OWNER_ID);
+        assert_eq!(
+            request[0]["text"],
+            format!(
+                "I've been added to Test (ID {}) by @alice, should I stay there? I'm ignoring it until you decide",
+                chat_id
+            )
+        );
+        assert_eq!(
+            request[0]["reply_markup"]["inline_keyboard"][0],
+            serde_json::json!([
+                { "text": "Approve", "callback_data": format!("allow_chat:{}", chat_id) },
+                { "text": "Reject", "callback_data": format!("deny_chat:{}", chat_id) },
+            ])
+        );
+
+        press_join_request_button(&bot, &owner, 0);
+        bot.send(update("message", message(3, &chat, &alice, "/gn")));
+
+        assert_eq!(
+            bot.replies()[1..],
+            [
+                (
+                    OWNER_ID,
+                    format!("Chat {} is allowed now", chat_id),
+                    Some(100)
+                ),
+                (chat_id, "Good night!".to_string(), Some(3)),
+            ]
+        );
+        assert_eq!(bot.client.calls_to("answerCallbackQuery").len(), 1);
+        assert!(bot.client.left_chats().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn rejected_join_request_denies_chat() {
+        let bot = TestBot::new("[allowed_chats]\nallow_unspecified = false");
+        let chat = group(random_id());
+        let chat_id = chat["id"].as_i64().unwrap();
+        let alice = user(random_id(), "alice");
+
+        bot.send(update("message", message(1, &chat, &alice, "/gn")));
+        press_join_request_button(&bot, &user(OWNER_ID, "owner"), 1);
+        bot.send(update("message", message(2, &chat, &alice, "/gn")));
+
+        assert_eq!(
+            bot.replies()[1..],
+            [(
+                OWNER_ID,
+                format!("Chat {} is denied now, I'm leaving it", chat_id),
+                Some(100)
+            )]
+        );
+        // Once for the button, once for the next message
+        assert_eq!(bot.client.left_chats(), vec![chat_id, chat_id]);
+    }
+
+    #[test]
+    #[ignore]
+    fn stored_permission_overrides_config() {
+        let group_id = random_id();
+        let bot = TestBot::new(&format!(
+            "[allowed_chats]\ngroup = {{ \"{}\" = false }}",
+            -group_id
+        ));
+        let chat = group(group_id);
+        let alice = user(random_id(), "alice");
+        let owner = user(OWNER_ID, "owner");
+
+        bot.send(update("message", message(1, &chat, &alice, "/gn")));
+        bot.send(update(
+            "message",
+            message(
+                2,
+                &private(&owner),
+                &owner,
+                &format!("/allow_chat {}", -group_id),
+            ),
+        ));
+        bot.send(update("message", message(3, &chat, &alice, "/gn")));
+
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (
+                    OWNER_ID,
+                    format!("Chat {} is allowed now", -group_id),
+                    Some(2)
+                ),
+                (-group_id, "Good night!".to_string(), Some(3)),
+            ]
+        );
+        // The config has denied it before /allow_chat
+        assert_eq!(bot.client.left_chats(), vec![-group_id]);
+    }
+
+    #[test]
+    #[ignore]
+    fn edited_commands_are_ignored_by_default() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update("message", message(1, &chat, &alice, "/cancel")));
+        let mut edited = message(1, &chat, &alice, "/cancel now");
+        edited["edit_date"] = 1_627_000_001.into();
+        bot.send(update("edited_message", edited));
+
+        assert_eq!(bot.replies().len(), 1);
+    }
+
+    #[test]
+    #[ignore]
+    fn edited_commands_are_rerun() {
+        let bot = TestBot::new("edited_commands = \"rerun\"");
+        let chat = group(random_id());
+        let alice = user(random_id(), "alice");
+
+        bot.send(update("message", message(1, &chat, &alice, "hi")));
+        bot.send(update("message", message(2, &chat, &alice, "/cancel")));
+        for (message_id, text) in [(1, "/cancel"), (2, "/cancel now")] {
+            let mut edited = message(message_id, &chat, &alice, text);
+            edited["edit_date"] = 1_627_000_001.into();
+            bot.send(update("edited_message", edited));
+        }
+
+        // A message edited into a command is a command too
+        let chat_id = chat["id"].as_i64().unwrap();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, "There's nothing to cancel".to_string(), Some(2)),
+                (chat_id, "There's nothing to cancel".to_string(), Some(1)),
+                (chat_id, "There's nothing to cancel".to_string(), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn weather_for_location_name() {
+        let bot = TestBot::new("");
+        bot.weather
+            .set_weather("London", "London", 12.34, "clear sky");
+        let chat = group(random_id());
+
+        bot.send(update(
+            "message",
+            message(60, &chat, &user(random_id(), "grace"), "/weather London"),
+        ));
+
+        assert_eq!(
+            bot.replies(),
+            vec![(
+                chat["id"].as_i64().unwrap(),
+                "London: +12.3 (feels like +11.3), ☀️ clear sky".to_string(),
+                Some(60)
+            )]
+        );
+        assert_eq!(bot.weather.requests(), vec!["london"]);
+    }
+
+    #[test]
+    #[ignore]
+    fn weather_for_unknown_location() {
+        let bot = TestBot::new("");
+        let chat = group(random_id());
+
+        bot.send(update(
+            "message",
+            message(70, &chat, &user(random_id(), "heidi"), "/weather Atlantis"),
+        ));
+
+        assert_eq!(
+            bot.replies(),
+            vec![(
+                chat["id"].as_i64().unwrap(),
+                "No weather data for this location found".to_string(),
+                Some(70)
+            )]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn weather_for_set_location() {
+        let bot = TestBot::new("");
+        bot.weather
+            .set_weather("51.5,-0.12", "London", -1.0, "clear sky");
+        let chat = group(random_id());
+        let ivan = user(random_id(), "ivan");
+
+        // A location sent to the chat is answered with its weather too
+        bot.send(update("message", location_message(80, &chat, &ivan)));
+        let mut set_location = message(81, &chat, &ivan, "/set_my_location");
+        set_location["reply_to_message"] = location_message(80, &chat, &ivan);
+        bot.send(update("message", set_location));
+        bot.send(update("message", message(82, &chat, &ivan, "/weather")));
+
+        let chat_id = chat["id"].as_i64().unwrap();
+        let weather = "London: -1.0 (feels like -2.0), ☀️ clear sky".to_string();
+        assert_eq!(
+            bot.replies(),
+            vec![
+                (chat_id, weather.clone(), Some(80)),
+                (chat_id, "Location set!".to_string(), Some(81)),
+                (chat_id, weather, Some(82)),
+            ]
+        );
+        assert_eq!(bot.weather.requests(), vec!["51.5,-0.12", "51.5,-0.12"]);
+    }
 }
@@ -0,0 +1,177 @@
+use std::error::Error;
+use std::io::Read;
+use std::net::SocketAddr;
+
+use frankenstein::Update;
+use tiny_http::{Method, Request, Response, Server};
+use tracing::warn;
+
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+pub struct WebhookServer {
+    server: Server,
+    secret_token: String,
+}
+
+impl WebhookServer {
+    pub fn bind(listen: &str, secret_token: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Self {
+            server: Server::http(listen)?,
+            secret_token: secret_token.to_string(),
+        })
+    }
+
+    pub fn server_addr(&self) -> SocketAddr {
+        self.server.server_addr()
+    }
+
+    // Blocks until Telegram sends a valid update. Returns None once the server stops accepting
+    // connections.
+    pub fn recv(&self) -> Option<Update> {
+        loop {
+            let request = self.server.recv().ok()?;
+            if let Some(update) = self.handle_request(request) {
+                return Some(update);
+            }
+        }
+    }
+
+    pub fn unblock(&self) {
+        self.server.unblock();
+    }
+
+    fn handle_request(&self, mut request: Request) -> Option<Update> {
+        if *request.method() != Method::Post {
+            let _ = request.respond(Response::empty(405));
+            return None;
+        }
+
+        // Telegram sends the secret token passed to setWebhook in a header, the requests
+        // without it are not coming from Telegram
+        let secret_token = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(SECRET_TOKEN_HEADER))
+            .map(|header| header.value.as_str());
+        if secret_token != Some(self.secret_token.as_str()) {
+            warn!("Rejected a webhook request to {}", request.url());
+            let _ = request.respond(Response::empty(401));
+            return None;
+        }
+
+        let mut body = String::new();
+        if let Err(err) = request.as_reader().read_to_string(&mut body) {
+            warn!("Failed to read the webhook request: {}", err);
+            let _ = request.respond(Response::empty(400));
+            return None;
+        }
+
+        match serde_json::from_str::<Update>(body.as_str()) {
+            Ok(update) => {
+                let _ = request.respond(Response::empty(200));
+                Some(update)
+            }
+            Err(err) => {
+                warn!("Failed to parse the webhook update: {}", err);
+                let _ = request.respond(Response::empty(400));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    const SECRET_TOKEN: &str = "some-long-random-string";
+
+    // Runs the server on a random port until the request is answered, returns the response
+    // status and the update the server has accepted
+    fn serve<F>(request: F) -> (u16, Option<Update>)
+    where
+        F: FnOnce(&str) -> Result<ureq::Response, ureq::Error>,
+    {
+        let server = Arc::new(WebhookServer::bind("127.0.0.1:0", SECRET_TOKEN).unwrap());
+        let url = format!("http://{}/", server.server_addr());
+
+        let receiver = Arc::clone(&server);
+        let handle = thread::spawn(move || receiver.recv());
+
+        let status = match request(url.as_str()) {
+            Ok(response) => response.status(),
+            Err(ureq::Error::Status(status, _)) => status,
+            Err(err) => panic!("The webhook request failed: {}", err),
+        };
+        server.unblock();
+
+        (status, handle.join().unwrap())
+    }
+
+    #[test]
+    fn accepts_update() {
+        let (status, update) = serve(|url| {
+            ureq::post(url)
+                .set(SECRET_TOKEN_HEADER, SECRET_TOKEN)
+                .send_string(r#"{"update_id": 42}"#)
+        });
+
+        assert_eq!(status, 200);
+        assert_eq!(update.map(|update| update.update_id), Some(42));
+    }
+
+    #[test]
+    fn rejects_wrong_secret_token() {
+        let (status, update) = serve(|url| {
+            ureq::post(url)
+                .set(SECRET_TOKEN_HEADER, "wrong")
+                .send_string(r#"{"update_id": 42}"#)
+        });
+
+        assert_eq!(status, 401);
+        assert!(update.is_none());
+    }
+
+    #[test]
+    fn rejects_missing_secret_token() {
+        let (status, update) = serve(|url| ureq::post(url).send_string(r#"{"update_id": 42}"#));
+
+        assert_eq!(status, 401);
+        assert!(update.is_none());
+    }
+
+    #[test]
+    fn rejects_secret_token_in_path() {
+        let (status, update) = serve(|url| {
+            ureq::post(format!("{}{}", url, SECRET_TOKEN).as_str())
+                .send_string(r#"{"update_id": 42}"#)
+        });
+
+        assert_eq!(status, 401);
+        assert!(update.is_none());
+    }
+
+    #[test]
+    fn rejects_non_post() {
+        let (status, update) =
+            serve(|url| ureq::get(url).set(SECRET_TOKEN_HEADER, SECRET_TOKEN).call());
+
+        assert_eq!(status, 405);
+        assert!(update.is_none());
+    }
+
+    #[test]
+    fn rejects_bad_json() {
+        let (status, update) = serve(|url| {
+            ureq::post(url)
+                .set(SECRET_TOKEN_HEADER, SECRET_TOKEN)
+                .send_string("{not json")
+        });
+
+        assert_eq!(status, 400);
+        assert!(update.is_none());
+    }
+}
@@ -0,0 +1,78 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use frankenstein::Update;
+use tracing::error;
+
+use crate::helpers;
+use crate::updates::UpdateHandler;
+
+struct Job {
+    update: Update,
+    done: Sender<()>,
+}
+
+pub struct WorkerPool {
+    senders: Vec<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize, handler: Arc<UpdateHandler>) -> Self {
+        let (senders, workers) = (0..size)
+            .map(|index| {
+                let (sender, receiver) = channel::<Job>();
+                let handler = Arc::clone(&handler);
+
+                let worker = thread::Builder::new()
+                    .name(format!("worker-{}", index))
+                    .spawn(move || {
+                        for Job { update, done } in receiver {
+                            // A panic would kill the worker and every chat it handles with it.
+                            // The panic itself is printed by the panic hook
+                            if catch_unwind(AssertUnwindSafe(|| handler.process_update(&update)))
+                                .is_err()
+                            {
+                                error!("Worker {} panicked on update {}", index, update.update_id);
+                            }
+                            // Nobody waits for the webhook updates, so the receiver may be gone
+                            let _ = done.send(());
+                        }
+                    })
+                    .expect("Failed to spawn a worker thread");
+
+                (sender, worker)
+            })
+            .unzip();
+
+        Self { senders, workers }
+    }
+
+    // Updates from the same chat always go to the same worker, so they are handled in the order
+    // Telegram sent them while the other chats don't have to wait. The returned receiver gets a
+    // message once the update is handled.
+    pub fn dispatch(&self, update: Update) -> Receiver<()> {
+        let key = helpers::get_chat_id_by_update(&update).unwrap_or(update.update_id as i64);
+        let index = key.rem_euclid(self.senders.len() as i64) as usize;
+
+        let (done, receiver) = channel();
+        if self.senders[index].send(Job { update, done }).is_err() {
+            error!("Worker {} is gone, the update is dropped", index);
+        }
+
+        receiver
+    }
+
+    // Closes the queues and waits for the workers to handle the updates that are already queued
+    pub fn shutdown(self) {
+        drop(self.senders);
+
+        for worker in self.workers {
+            if worker.join().is_err() {
+                error!("A worker thread panicked");
+            }
+        }
+    }
+}